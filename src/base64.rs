@@ -13,9 +13,66 @@ pub fn encode(num: i32) -> Option<char> {
     }
 }
 
+/// The inverse of `encode`: the value of a single base64 digit.
+pub fn decode(c: u8) -> Option<i32> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as i32),
+        b'a'..=b'z' => Some((c - b'a') as i32 + 26),
+        b'0'..=b'9' => Some((c - b'0') as i32 + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Encode arbitrary bytes with the standard (RFC 4648) base64 alphabet,
+/// including `=` padding. This is what `data:` URLs expect.
+pub fn encode_bytes(input: &[u8]) -> String {
+    let mut encoded = String::with_capacity(input.len().div_ceil(3) * 4);
+
+    for chunk in input.chunks(3) {
+        let b0 = chunk[0] as i32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as i32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as i32;
+
+        encoded.push(encode(b0 >> 2).unwrap());
+        encoded.push(encode(((b0 & 0b11) << 4) | (b1 >> 4)).unwrap());
+        if chunk.len() > 1 {
+            encoded.push(encode(((b1 & 0b1111) << 2) | (b2 >> 6)).unwrap());
+        } else {
+            encoded.push('=');
+        }
+        if chunk.len() > 2 {
+            encoded.push(encode(b2 & 0b111111).unwrap());
+        } else {
+            encoded.push('=');
+        }
+    }
+
+    encoded
+}
+
 #[test]
 fn test_encode() {
     assert_eq!(encode(0), Some('A'));
     assert_eq!(encode(26), Some('a'));
     assert_eq!(encode(64), None);
 }
+
+#[test]
+fn test_decode() {
+    for num in 0..64 {
+        assert_eq!(decode(encode(num).unwrap() as u8), Some(num));
+    }
+    assert_eq!(decode(b'='), None);
+}
+
+#[test]
+fn test_encode_bytes() {
+    assert_eq!(encode_bytes(b""), "");
+    assert_eq!(encode_bytes(b"f"), "Zg==");
+    assert_eq!(encode_bytes(b"fo"), "Zm8=");
+    assert_eq!(encode_bytes(b"foo"), "Zm9v");
+    assert_eq!(encode_bytes(b"foobar"), "Zm9vYmFy");
+    assert_eq!(encode_bytes(&[0xff, 0xfe]), "//4=");
+}
@@ -1,4 +1,5 @@
 use crate::base64;
+use std::convert::TryFrom;
 
 // A single base 64 digit can contain 6 bits of data. For the base 64 variable
 // length quantities we use in the source map spec, the first bit is the sign,
@@ -31,6 +32,37 @@ fn to_vlq_signed(value: i32) -> i32 {
     }
 }
 
+fn from_vlq_signed(value: i64) -> i64 {
+    let shifted = value >> 1;
+    if value & 1 == 1 {
+        -shifted
+    } else {
+        shifted
+    }
+}
+
+/// Decode one VLQ value from the start of `input`. Returns the value and the
+/// number of bytes it took, or `None` if `input` doesn't start with a complete
+/// value that fits in an `i32`.
+pub fn base64vlq_decode(input: &[u8]) -> Option<(i32, usize)> {
+    let mut result: i64 = 0;
+    let mut shift = 0;
+
+    for (index, c) in input.iter().enumerate() {
+        let digit = base64::decode(*c)? as i64;
+        result += (digit & VLQ_BASE_MASK as i64) << shift;
+        if shift > 32 {
+            return None;
+        }
+        if digit & VLQ_CONTINUATION_BIT as i64 == 0 {
+            let value = i32::try_from(from_vlq_signed(result)).ok()?;
+            return Some((value, index + 1));
+        }
+        shift += VLQ_BASE_SHIFT;
+    }
+    None
+}
+
 pub fn base64vlq_encode(value: i32) -> String {
     let mut encoded = String::new();
     let mut digit: i32;
@@ -62,6 +94,21 @@ fn test_to_vlq_signed() {
     assert_eq!(to_vlq_signed(-2), 0b101)
 }
 
+#[test]
+fn test_base64vlq_decode() {
+    for value in [-255, -1, 0, 1, 16, 255, 1 << 20, -(1 << 28)] {
+        let encoded = base64vlq_encode(value);
+        assert_eq!(
+            base64vlq_decode(encoded.as_bytes()),
+            Some((value, encoded.len()))
+        );
+    }
+    assert_eq!(base64vlq_decode(b"gB,A"), Some((16, 2)));
+    assert_eq!(base64vlq_decode(b"g"), None);
+    assert_eq!(base64vlq_decode(b",A"), None);
+    assert_eq!(base64vlq_decode(b"ggggggggA"), None);
+}
+
 #[test]
 fn test_base64vlq_encode() {
     let vlqs = [
@@ -0,0 +1,189 @@
+use crate::line_index::{ColumnUnit, LineIndex};
+
+const RESET: &str = "\x1b[0m";
+const GUTTER: &str = "\x1b[90m";
+const MARKER: &str = "\x1b[31m\x1b[1m";
+
+#[derive(Debug, Clone)]
+pub struct CodeFrameOptions {
+    /// How many lines to show above and below the highlighted one.
+    pub context_lines: usize,
+    /// Highlight the frame with ANSI escape codes.
+    pub color: bool,
+}
+
+impl Default for CodeFrameOptions {
+    fn default() -> Self {
+        CodeFrameOptions {
+            context_lines: 2,
+            color: false,
+        }
+    }
+}
+
+///
+/// Render `source` around `line` with a marker under `column`. When
+/// `end_column` is given the marker underlines everything up to it, otherwise
+/// it is a single caret. Lines are 1-based and columns are 0-based UTF-16 code
+/// units, as in a source map.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::code_frame::{render, CodeFrameOptions};
+///
+/// let source = "function foo() {\n  return bar;\n}";
+/// let frame = render(source, 2, 9, Some(12), &CodeFrameOptions::default());
+/// assert_eq!(
+///     frame,
+///     "  1 | function foo() {\n> 2 |   return bar;\n    |          ^^^\n  3 | }\n"
+/// );
+/// ```
+///
+pub fn render(
+    source: &str,
+    line: i32,
+    column: i32,
+    end_column: Option<i32>,
+    options: &CodeFrameOptions,
+) -> String {
+    let index = LineIndex::new(source);
+    let line_count = index.line_count() as i32;
+    if line < 1 || line > line_count {
+        return String::new();
+    }
+
+    let context = options.context_lines as i32;
+    let first = (line - context).max(1);
+    let last = (line + context).min(line_count);
+    let gutter_width = last.to_string().len();
+
+    let paint = |style: &str, text: &str| -> String {
+        if options.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+
+    let mut frame = String::new();
+    for current in first..=last {
+        let text = index.line(current).unwrap_or_default();
+        let is_marked = current == line;
+        let gutter = format!(
+            "{} {:>width$} |",
+            if is_marked { ">" } else { " " },
+            current,
+            width = gutter_width
+        );
+
+        if is_marked {
+            frame.push_str(&paint(MARKER, ">"));
+            frame.push_str(&paint(GUTTER, &gutter[1..]));
+        } else {
+            frame.push_str(&paint(GUTTER, &gutter));
+        }
+        if !text.is_empty() {
+            frame.push(' ');
+            frame.push_str(text);
+        }
+        frame.push('\n');
+
+        if is_marked {
+            frame.push_str(&paint(
+                GUTTER,
+                &format!("  {:>width$} |", "", width = gutter_width),
+            ));
+            frame.push(' ');
+            frame.push_str(&marker_line(&index, line, column, end_column, options));
+            frame.push('\n');
+        }
+    }
+
+    frame
+}
+
+fn marker_line(
+    index: &LineIndex,
+    line: i32,
+    column: i32,
+    end_column: Option<i32>,
+    options: &CodeFrameOptions,
+) -> String {
+    let text = index.line(line).unwrap_or_default();
+    let to_chars = |column: i32| {
+        index
+            .convert_column(line, column, ColumnUnit::Utf16, ColumnUnit::CodePoint)
+            .unwrap_or(column)
+            .max(0) as usize
+    };
+
+    let start = to_chars(column);
+    let end = match end_column {
+        Some(end_column) if end_column > column => to_chars(end_column),
+        _ => start + 1,
+    };
+
+    // Keep tabs so the marker lines up with the code above it.
+    let padding: String = text
+        .chars()
+        .chain(std::iter::repeat(' '))
+        .take(start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let marker = "^".repeat((end - start).max(1));
+
+    if options.color {
+        format!("{}{}{}{}", padding, MARKER, marker, RESET)
+    } else {
+        format!("{}{}", padding, marker)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+
+    #[test]
+    fn context_lines() {
+        let options = CodeFrameOptions {
+            context_lines: 1,
+            color: false,
+        };
+        assert_eq!(
+            render(SOURCE, 10, 0, None, &options),
+            "   9 | i\n> 10 | j\n     | ^\n  11 | k\n"
+        );
+        assert_eq!(
+            render(SOURCE, 1, 0, None, &options),
+            "> 1 | a\n    | ^\n  2 | b\n"
+        );
+        assert_eq!(render(SOURCE, 12, 0, None, &options), "");
+    }
+
+    #[test]
+    fn wide_characters_and_tabs() {
+        let options = CodeFrameOptions {
+            context_lines: 0,
+            color: false,
+        };
+        // "😀" is two UTF-16 code units but a single character on screen.
+        assert_eq!(
+            render("\t\"😀\", foo", 1, 7, Some(10), &options),
+            "> 1 | \t\"😀\", foo\n    | \t     ^^^\n"
+        );
+    }
+
+    #[test]
+    fn color() {
+        let options = CodeFrameOptions {
+            context_lines: 0,
+            color: true,
+        };
+        assert_eq!(
+            render("foo", 1, 0, None, &options),
+            "\x1b[31m\x1b[1m>\x1b[0m\x1b[90m 1 |\x1b[0m foo\n\x1b[90m    |\x1b[0m \x1b[31m\x1b[1m^\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,429 @@
+//!
+//! Concatenate generated files and their source maps without decoding the
+//! maps into `Mapping`s and encoding them again.
+//!
+//! Every VLQ field but the generated column is relative to the previous
+//! segment of the whole `mappings` string, so appending one map to another
+//! only invalidates the first segment that has a source and the first one that
+//! has a name. Those are re-encoded against the end state of the maps before
+//! them; all other segments are copied as they are.
+//!
+use crate::base64_vlq::{base64vlq_decode, base64vlq_encode};
+use crate::line_index::LineIndex;
+use crate::source_map::SourceMapJson;
+use crate::util;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConcatError {
+    /// The map at this index has `sections`.
+    IndexedMap(usize),
+    /// The `mappings` of the map at this index are not valid VLQ.
+    InvalidMappings(usize),
+    /// The map at this index has mappings past the last line of its code.
+    TooManyLines(usize),
+}
+
+impl fmt::Display for ConcatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConcatError::IndexedMap(index) => {
+                write!(f, "map {} is an indexed map, which is not supported", index)
+            }
+            ConcatError::InvalidMappings(index) => write!(f, "map {} has invalid mappings", index),
+            ConcatError::TooManyLines(index) => {
+                write!(f, "map {} has mappings past the end of its code", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConcatError {}
+
+/// Running values of the relative fields: source, original line, original
+/// column and name.
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    source: i32,
+    line: i32,
+    column: i32,
+    name: i32,
+}
+
+/// A segment that has to be re-encoded, as a byte range of `mappings` and its
+/// decoded fields.
+struct Segment {
+    start: usize,
+    end: usize,
+    fields: Vec<i32>,
+}
+
+/// The result of scanning one map's `mappings`.
+struct Scan {
+    first_source: Option<Segment>,
+    first_name: Option<Segment>,
+    /// Values after the last segment, relative to the start of the map.
+    end: State,
+    has_sources: bool,
+    has_names: bool,
+}
+
+fn scan(mappings: &[u8]) -> Option<Scan> {
+    let mut scan = Scan {
+        first_source: None,
+        first_name: None,
+        end: State::default(),
+        has_sources: false,
+        has_names: false,
+    };
+
+    let mut index = 0;
+    while index < mappings.len() {
+        if mappings[index] == b';' || mappings[index] == b',' {
+            index += 1;
+            continue;
+        }
+
+        let start = index;
+        let mut fields = vec![];
+        while index < mappings.len() && mappings[index] != b';' && mappings[index] != b',' {
+            let (value, length) = base64vlq_decode(&mappings[index..])?;
+            fields.push(value);
+            index += length;
+        }
+
+        match fields.len() {
+            1 => {}
+            4 | 5 => {
+                scan.end.source = scan.end.source.checked_add(fields[1])?;
+                scan.end.line = scan.end.line.checked_add(fields[2])?;
+                scan.end.column = scan.end.column.checked_add(fields[3])?;
+                if !scan.has_sources {
+                    scan.has_sources = true;
+                    scan.first_source = Some(Segment {
+                        start,
+                        end: index,
+                        fields: fields.clone(),
+                    });
+                }
+                if fields.len() == 5 {
+                    scan.end.name = scan.end.name.checked_add(fields[4])?;
+                    if !scan.has_names {
+                        scan.has_names = true;
+                        scan.first_name = Some(Segment {
+                            start,
+                            end: index,
+                            fields,
+                        });
+                    }
+                }
+            }
+            _ => return None,
+        }
+        let end = &scan.end;
+        if end.source < 0 || end.line < 0 || end.column < 0 || end.name < 0 {
+            return None;
+        }
+    }
+
+    Some(scan)
+}
+
+/// Encode `segment` relative to `previous` instead of the start of its own
+/// map, with its source and name indexes shifted by `offset`.
+fn rebase(segment: &Segment, offset: &State, previous: &State, only_name: bool) -> String {
+    let mut fields = segment.fields.clone();
+    if !only_name {
+        fields[1] += offset.source - previous.source;
+        fields[2] -= previous.line;
+        fields[3] -= previous.column;
+    }
+    if fields.len() == 5 {
+        fields[4] += offset.name - previous.name;
+    }
+    fields.into_iter().map(base64vlq_encode).collect()
+}
+
+///
+/// Join `inputs`, pairs of generated code and its source map, into one file
+/// and one map. The code is joined with newlines so that every input starts on
+/// a new line. `sources`, `sourcesContent` and `names` are appended in order,
+/// without removing duplicates, and `sourceRoot` is folded into `sources`.
+/// The `ignoreList`s are merged.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::concat::concat;
+/// use rusty_source_map::source_map::SourceMapJson;
+///
+/// let a: SourceMapJson =
+///     serde_json::from_str(r#"{"version":3,"sources":["a.js"],"names":["x"],"mappings":"AAAAA;AACA"}"#).unwrap();
+/// let b: SourceMapJson =
+///     serde_json::from_str(r#"{"version":3,"sources":["b.js"],"names":["y"],"mappings":"AAAAA"}"#).unwrap();
+///
+/// let (code, map) = concat(&[("x;\nx;", &a), ("y;", &b)], Some("out.js".to_string())).unwrap();
+/// assert_eq!(code, "x;\nx;\ny;");
+/// assert_eq!(map.sources, Some(vec!["a.js".to_string(), "b.js".to_string()]));
+/// assert_eq!(map.mappings.as_deref(), Some("AAAAA;AACA;ACDAC"));
+/// ```
+///
+pub fn concat(
+    inputs: &[(&str, &SourceMapJson)],
+    file: Option<String>,
+) -> Result<(String, SourceMapJson), ConcatError> {
+    let mut code = String::new();
+    let mut mappings = String::new();
+    let mut sources: Vec<String> = vec![];
+    let mut names: Vec<String> = vec![];
+    let mut contents: Vec<Option<String>> = vec![];
+    let mut ignore_list: Vec<u32> = vec![];
+
+    let mut previous = State::default();
+    // Generated line the next input starts on, and the line `mappings` is at.
+    let mut line_offset = 0;
+    let mut mappings_line = 0;
+
+    for (index, (input_code, map)) in inputs.iter().enumerate() {
+        if map.sections.is_some() {
+            return Err(ConcatError::IndexedMap(index));
+        }
+        if index > 0 {
+            code.push('\n');
+        }
+        code.push_str(input_code);
+
+        let offset = State {
+            source: sources.len() as i32,
+            name: names.len() as i32,
+            ..Default::default()
+        };
+        for (i, source) in map.sources.iter().flatten().enumerate() {
+            sources.push(match map.source_root.as_deref() {
+                Some(root) if !root.is_empty() => {
+                    util::compute_source_url(Some(root), source, None)
+                }
+                _ => source.clone(),
+            });
+            contents.push(
+                map.sources_content
+                    .as_ref()
+                    .and_then(|it| it.get(i).cloned().flatten()),
+            );
+        }
+        names.extend(map.names.iter().flatten().cloned());
+        ignore_list.extend(
+            map.ignored_source_indexes()
+                .iter()
+                .map(|it| it + offset.source as u32),
+        );
+
+        let input_mappings = map
+            .mappings
+            .as_deref()
+            .unwrap_or_default()
+            .trim_end_matches(';');
+        let lines = input_mappings.matches(';').count();
+        let code_lines = LineIndex::new(input_code).line_count();
+        if !input_mappings.is_empty() && lines >= code_lines {
+            return Err(ConcatError::TooManyLines(index));
+        }
+        let scan = scan(input_mappings.as_bytes()).ok_or(ConcatError::InvalidMappings(index))?;
+
+        if !input_mappings.is_empty() {
+            for _ in mappings_line..line_offset {
+                mappings.push(';');
+            }
+
+            // Re-encode the (at most two) segments whose relative fields
+            // refer to the previous map, and copy everything else.
+            let mut rewrites = vec![];
+            if let Some(segment) = &scan.first_source {
+                rewrites.push((segment, rebase(segment, &offset, &previous, false)));
+            }
+            if let Some(segment) = &scan.first_name {
+                if scan
+                    .first_source
+                    .as_ref()
+                    .is_none_or(|it| it.start != segment.start)
+                {
+                    rewrites.push((segment, rebase(segment, &offset, &previous, true)));
+                }
+            }
+            rewrites.sort_by_key(|(segment, _)| segment.start);
+
+            let mut copied = 0;
+            for (segment, encoded) in rewrites {
+                mappings.push_str(&input_mappings[copied..segment.start]);
+                mappings.push_str(&encoded);
+                copied = segment.end;
+            }
+            mappings.push_str(&input_mappings[copied..]);
+            mappings_line = line_offset + lines;
+        }
+
+        if scan.has_sources {
+            previous.source = offset.source + scan.end.source;
+            previous.line = scan.end.line;
+            previous.column = scan.end.column;
+        }
+        if scan.has_names {
+            previous.name = offset.name + scan.end.name;
+        }
+        line_offset += code_lines;
+    }
+
+    let has_contents = inputs.iter().any(|it| it.1.sources_content.is_some());
+    Ok((
+        code,
+        SourceMapJson {
+            version: 3,
+            sources: Some(sources),
+            names: Some(names),
+            mappings: Some(mappings),
+            file,
+            source_root: None,
+            sources_content: if has_contents { Some(contents) } else { None },
+            ignore_list: if ignore_list.is_empty() {
+                None
+            } else {
+                Some(ignore_list)
+            },
+            ..Default::default()
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::BasicConsumer;
+    use crate::generator::SourceMapGenerator;
+    use crate::mapping::Mapping;
+    use crate::source_map::Position;
+
+    fn map(json: &str) -> SourceMapJson {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// Decode `map` into `(generated, source, original, name)` tuples.
+    fn decoded(map: SourceMapJson) -> Vec<(Position, String, Position, Option<String>)> {
+        let mut consumer = BasicConsumer::from_source_map_json(map, None);
+        consumer
+            .mappings_in_generated_range(
+                Position { line: 1, column: 0 },
+                Position {
+                    line: 100,
+                    column: 0,
+                },
+            )
+            .into_iter()
+            .filter_map(|it: Mapping| Some((it.generated, it.source?, it.original?, it.name)))
+            .collect()
+    }
+
+    #[test]
+    fn matches_full_decode() {
+        let a = map(r#"{
+          "version": 3,
+          "sources": ["a1.js", "a2.js"],
+          "sourcesContent": ["A1", "A2"],
+          "names": ["p", "q"],
+          "mappings": "AAAA,CCEEC;ADAAD,E;;"
+        }"#);
+        // The first segment with a name comes after the first with a source.
+        let b = map(r#"{
+          "version": 3,
+          "sourceRoot": "lib/",
+          "sources": ["b.js"],
+          "names": ["r"],
+          "mappings": ";IAIE,EAAEA"
+        }"#);
+        let c = map(r#"{"version":3,"sources":[],"names":[],"mappings":""}"#);
+        let d = map(r#"{"version":3,"sources":["d.js"],"names":[],"mappings":"A,CAAC"}"#);
+
+        let inputs = [
+            ("a a;\nb b", &a),
+            ("\n    b  b", &b),
+            ("no map", &c),
+            ("dd", &d),
+        ];
+        let (code, combined) = concat(&inputs, None).unwrap();
+        assert_eq!(code, "a a;\nb b\n\n    b  b\nno map\ndd");
+        assert_eq!(
+            combined.sources,
+            Some(vec![
+                "a1.js".to_string(),
+                "a2.js".to_string(),
+                "lib/b.js".to_string(),
+                "d.js".to_string()
+            ])
+        );
+        assert_eq!(
+            combined.sources_content,
+            Some(vec![
+                Some("A1".to_string()),
+                Some("A2".to_string()),
+                None,
+                None
+            ])
+        );
+
+        // Build the same map the slow way and compare what both decode to.
+        let mut expected = vec![];
+        let mut line_offset = 0;
+        for (code, map) in &inputs {
+            for (mut generated, source, original, name) in decoded((*map).clone()) {
+                generated.line += line_offset;
+                expected.push((generated, source, original, name));
+            }
+            line_offset += LineIndex::new(code).line_count() as i32;
+        }
+        let mut generator = SourceMapGenerator::new(None, None, true);
+        for (generated, source, original, name) in &expected {
+            generator.add_mapping(Mapping {
+                generated: generated.clone(),
+                original: Some(original.clone()),
+                source: Some(source.clone()),
+                name: name.clone(),
+                last_generated_column: None,
+            });
+        }
+
+        assert_eq!(decoded(combined), decoded(generator.as_json()));
+        assert_eq!(decoded(generator.as_json()).len(), 6);
+    }
+
+    #[test]
+    fn errors() {
+        let indexed = map(r#"{"version":3,"sections":[]}"#);
+        let invalid = map(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AA!A"}"#);
+        let long = map(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA;AAAA"}"#);
+        let ok = map(r#"{"version":3,"sources":[],"names":[],"mappings":""}"#);
+
+        assert_eq!(
+            concat(&[("", &ok), ("", &indexed)], None).err(),
+            Some(ConcatError::IndexedMap(1))
+        );
+        assert_eq!(
+            concat(&[("a", &invalid)], None).err(),
+            Some(ConcatError::InvalidMappings(0))
+        );
+        assert_eq!(
+            concat(&[("a", &long)], None).err(),
+            Some(ConcatError::TooManyLines(0))
+        );
+        assert!(concat(&[("a\nb", &long)], None).is_ok());
+
+        // A line that overflows, and a negative source.
+        for mappings in ["AA+/////D,AA+/////D", "ADAA"] {
+            let invalid = map(&format!(
+                r#"{{"version":3,"sources":["a.js"],"names":[],"mappings":"{}"}}"#,
+                mappings
+            ));
+            assert_eq!(
+                concat(&[("a", &invalid)], None).err(),
+                Some(ConcatError::InvalidMappings(0))
+            );
+        }
+    }
+}
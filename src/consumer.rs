@@ -1,35 +1,182 @@
 use crate::array_set::ArraySet;
+use crate::code_frame::{self, CodeFrameOptions};
+use crate::function_map::{self, FunctionMapping};
 use crate::generator::SourceMapGenerator;
+use crate::lenient::{self, Warning};
+use crate::limits::{self, LimitError, Limits};
+use crate::line_index::{ColumnUnit, LineIndex};
 use crate::mapping::Mapping;
+use crate::scopes::{OriginalScope, Scopes};
 use crate::source_map::{Position, SourceMapJson};
-use crate::{binary_search, util};
+use crate::{binary_search, range_mappings, util};
 use rayon::prelude::*;
 use source_map_mappings::Bias;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 use std::panic;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
 ///
-/// You should always use this function to create consumer
+/// You should always use this function to create consumer. It puts no caps
+/// on the size of the map and panics on invalid mappings at the first lookup.
+/// For maps from untrusted sources, use `create_consumer_with_options` with
+/// `ParseOptions::limits` set.
 ///
 pub fn create_consumer(
     source_map_raw: &str,
     source_map_url: Option<&str>,
 ) -> Result<Consumer, serde_json::Error> {
     let source_map = serde_json::from_str::<SourceMapJson>(source_map_raw)?;
+    Ok(consumer_from_json(source_map, source_map_url))
+}
+
+/// How `create_consumer_with_options` parses a map.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Repair the spec deviations of known producers rather than failing,
+    /// see `lenient::parse`.
+    pub lenient: bool,
+    /// Reject maps over these caps, and maps whose `mappings` don't decode.
+    /// Set this for maps from untrusted sources.
+    pub limits: Option<Limits>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Json(serde_json::Error),
+    Limit(LimitError),
+    Mappings(source_map_mappings::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Json(error) => write!(f, "{}", error),
+            ParseError::Limit(error) => write!(f, "{}", error),
+            ParseError::Mappings(error) => {
+                let message = match error {
+                    source_map_mappings::Error::UnexpectedNegativeNumber => {
+                        "a line, column, source or name index is negative"
+                    }
+                    source_map_mappings::Error::UnexpectedlyBigNumber => {
+                        "a line, column, source or name index is over u32::MAX"
+                    }
+                    source_map_mappings::Error::VlqUnexpectedEof => "a value is cut off",
+                    source_map_mappings::Error::VlqInvalidBase64 => {
+                        "a value has a character that isn't base64"
+                    }
+                    source_map_mappings::Error::VlqOverflow => "a value overflows",
+                };
+                write!(f, "invalid mappings: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(error: serde_json::Error) -> Self {
+        ParseError::Json(error)
+    }
+}
+
+impl From<LimitError> for ParseError {
+    fn from(error: LimitError) -> Self {
+        ParseError::Limit(error)
+    }
+}
+
+impl From<source_map_mappings::Error> for ParseError {
+    fn from(error: source_map_mappings::Error) -> Self {
+        ParseError::Mappings(error)
+    }
+}
+
+///
+/// Like `create_consumer`, with the parse configured by `options`. Returns
+/// the repairs made to the map along with the consumer.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::consumer::{create_consumer_with_options, ParseError, ParseOptions};
+/// use rusty_source_map::lenient::Warning;
+/// use rusty_source_map::limits::{LimitError, Limits};
+///
+/// let raw = r#"{"version":"3","sources":["a.js"],"names":[],"mappings":"AAAA;;;"}"#;
+/// let options = ParseOptions {
+///     lenient: true,
+///     ..Default::default()
+/// };
+/// let (_consumer, warnings) = create_consumer_with_options(raw, None, &options).unwrap();
+/// assert_eq!(warnings, vec![Warning::StringVersion("3".to_string())]);
+///
+/// let options = ParseOptions {
+///     lenient: true,
+///     limits: Some(Limits {
+///         max_lines: 2,
+///         ..Default::default()
+///     }),
+/// };
+/// assert!(matches!(
+///     create_consumer_with_options(raw, None, &options),
+///     Err(ParseError::Limit(LimitError::Lines(2)))
+/// ));
+/// ```
+///
+pub fn create_consumer_with_options(
+    source_map_raw: &str,
+    source_map_url: Option<&str>,
+    options: &ParseOptions,
+) -> Result<(Consumer, Vec<Warning>), ParseError> {
+    if let Some(limits) = &options.limits {
+        limits::check_json_size(source_map_raw, limits)?;
+    }
+    let (source_map, warnings) = if options.lenient {
+        lenient::parse(source_map_raw)?
+    } else {
+        (
+            serde_json::from_str::<SourceMapJson>(source_map_raw)?,
+            vec![],
+        )
+    };
+    if let Some(limits) = &options.limits {
+        limits::check(&source_map, limits)?;
+    }
+    let mut consumer = consumer_from_json(source_map, source_map_url);
+    if options.limits.is_some() {
+        // Within the caps decoding is cheap, so do it now rather than panic
+        // on invalid mappings at the first lookup.
+        match &mut consumer {
+            Consumer::BasicConsumer(consumer) => consumer.decode_mappings()?,
+            Consumer::IndexedConsumer(consumer) => {
+                for section in consumer.sections.borrow_mut().iter_mut() {
+                    section.consumer.decode_mappings()?;
+                }
+            }
+        }
+    }
+    Ok((consumer, warnings))
+}
+
+fn consumer_from_json(source_map: SourceMapJson, source_map_url: Option<&str>) -> Consumer {
     if source_map.sections.is_some() {
-        Ok(Consumer::IndexedConsumer(
-            IndexedConsumer::from_source_map_json(source_map, source_map_url),
+        Consumer::IndexedConsumer(IndexedConsumer::from_source_map_json(
+            source_map,
+            source_map_url,
         ))
     } else {
-        Ok(Consumer::BasicConsumer(
-            BasicConsumer::from_source_map_json(source_map, source_map_url),
+        Consumer::BasicConsumer(BasicConsumer::from_source_map_json(
+            source_map,
+            source_map_url,
         ))
     }
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum Consumer {
     BasicConsumer(BasicConsumer),
     IndexedConsumer(IndexedConsumer),
@@ -99,6 +246,92 @@ impl Consumer {
             None
         }
     }
+
+    pub fn original_position_for(
+        &mut self,
+        generated: Position,
+        bias: Option<Bias>,
+    ) -> Option<Mapping> {
+        match self {
+            Self::BasicConsumer(consumer) => consumer.original_position_for(generated, bias),
+            Self::IndexedConsumer(consumer) => consumer.original_position_for(generated, bias),
+        }
+    }
+
+    pub fn mappings_in_generated_range(&mut self, start: Position, end: Position) -> Vec<Mapping> {
+        match self {
+            Self::BasicConsumer(consumer) => consumer.mappings_in_generated_range(start, end),
+            Self::IndexedConsumer(consumer) => consumer.mappings_in_generated_range(start, end),
+        }
+    }
+
+    /// Every mapping of the map in generated order, with the sections of an
+    /// indexed map flattened into one list.
+    pub fn all_mappings(&mut self) -> Vec<Mapping> {
+        self.mappings_in_generated_range(
+            Position { line: 1, column: 0 },
+            Position {
+                line: i32::MAX,
+                column: 0,
+            },
+        )
+    }
+
+    pub fn get_sources(&self) -> Vec<String> {
+        match self {
+            Self::BasicConsumer(consumer) => consumer.get_sources(),
+            Self::IndexedConsumer(consumer) => consumer.get_sources(),
+        }
+    }
+
+    pub fn debug_id(&self) -> Option<&str> {
+        match self {
+            Self::BasicConsumer(consumer) => consumer.debug_id(),
+            Self::IndexedConsumer(consumer) => consumer.debug_id(),
+        }
+    }
+
+    pub fn is_ignored(&mut self, source: &str) -> bool {
+        match self {
+            Self::BasicConsumer(consumer) => consumer.is_ignored(source),
+            Self::IndexedConsumer(consumer) => consumer.is_ignored(source),
+        }
+    }
+
+    pub fn function_name_for(&mut self, source: &str, original: Position) -> Option<String> {
+        match self {
+            Self::BasicConsumer(consumer) => consumer.function_name_for(source, original),
+            Self::IndexedConsumer(consumer) => consumer.function_name_for(source, original),
+        }
+    }
+
+    ///
+    /// The name of the original function the code at `generated` belongs to,
+    /// for naming stack frames. Taken from the map's scopes if it has any,
+    /// else from the Metro function map of the source it maps to.
+    ///
+    pub fn original_function_name_for(&mut self, generated: Position) -> Option<String> {
+        if let Self::BasicConsumer(consumer) = self {
+            if let Some(name) = consumer.original_function_name_at(generated.clone()) {
+                return Some(name.to_string());
+            }
+        }
+        let mapping = self.original_position_for(generated, None)?;
+        self.function_name_for(mapping.source.as_deref()?, mapping.original?)
+    }
+
+    pub fn source_content_for(
+        &mut self,
+        source: &str,
+        panic_on_missing: Option<bool>,
+    ) -> Option<String> {
+        match self {
+            Self::BasicConsumer(consumer) => consumer.source_content_for(source, panic_on_missing),
+            Self::IndexedConsumer(consumer) => {
+                consumer.source_content_for(source, panic_on_missing)
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -112,6 +345,41 @@ pub trait ConsumerTrait: Sized {
     fn each_mapping(&mut self, f: impl Fn(&source_map_mappings::Mapping), ord: IterOrd);
 }
 
+/// Whether the span `column..end_column` on `line` intersects the half-open
+/// range `start..end`. A missing `end_column` means the span runs to the end
+/// of the line.
+fn span_overlaps(
+    line: i32,
+    column: i32,
+    end_column: Option<i32>,
+    start: &Position,
+    end: &Position,
+) -> bool {
+    if line < start.line || line > end.line {
+        return false;
+    }
+    // A zero-width range still hits the mapping it points into.
+    if start.line == end.line && start.column == end.column {
+        return column <= start.column && end_column.is_none_or(|it| start.column < it);
+    }
+    let low = if line == start.line {
+        column.max(start.column)
+    } else {
+        column
+    };
+    let high = match end_column {
+        Some(end_column) if line == end.line => end_column.min(end.column),
+        Some(end_column) => end_column,
+        None if line == end.line => end.column,
+        None => i32::MAX,
+    };
+    low < high
+}
+
+fn position_cmp(a: &Position, b: &Position) -> std::cmp::Ordering {
+    (a.line, a.column).cmp(&(b.line, b.column))
+}
+
 pub struct BasicConsumer {
     pub source_map: SourceMapJson,
     pub(crate) source_lookup_cache: HashMap<String, i32>,
@@ -119,6 +387,10 @@ pub struct BasicConsumer {
     pub(crate) source_map_url: Option<String>,
     pub(crate) mappings: Option<source_map_mappings::Mappings>,
     pub(crate) computed_column_spans: bool,
+    pub(crate) source_line_indexes: HashMap<u32, Arc<LineIndex>>,
+    pub(crate) scopes: Option<Scopes>,
+    pub(crate) range_mappings: Option<HashSet<(u32, u32)>>,
+    pub(crate) function_maps: HashMap<u32, Vec<FunctionMapping>>,
 }
 impl BasicConsumer {
     pub fn new(source_map_raw: &str, source_map_url: Option<&str>) -> Self {
@@ -145,6 +417,10 @@ impl BasicConsumer {
             ),
             mappings: None,
             computed_column_spans: false,
+            source_line_indexes: Default::default(),
+            scopes: None,
+            range_mappings: None,
+            function_maps: Default::default(),
         }
     }
 
@@ -171,6 +447,10 @@ impl BasicConsumer {
             ),
             mappings: None,
             computed_column_spans: false,
+            source_line_indexes: Default::default(),
+            scopes: None,
+            range_mappings: None,
+            function_maps: Default::default(),
         }
     }
 
@@ -229,17 +509,29 @@ impl BasicConsumer {
 
     fn parse_mappings(&self) -> Result<source_map_mappings::Mappings, source_map_mappings::Error> {
         source_map_mappings::parse_mappings::<()>(
-            self.source_map.mappings.as_ref().unwrap().as_bytes(),
+            self.source_map
+                .mappings
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes(),
         )
     }
 
+    /// Decode the mappings now rather than at the first lookup.
+    fn decode_mappings(&mut self) -> Result<(), source_map_mappings::Error> {
+        if self.mappings.is_none() {
+            self.mappings = Some(self.parse_mappings()?);
+        }
+        Ok(())
+    }
+
     pub fn all_generated_position_for(
         &mut self,
         source: &str,
         original_line: i32,
         original_column: Option<i32>,
     ) -> Vec<source_map_mappings::Mapping> {
-        let original_column = if let Some(r) = original_column { r } else { 0 };
+        let original_column = original_column.unwrap_or_default();
         let source = self.find_source_index(source);
         if source.is_none() {
             return vec![];
@@ -318,19 +610,32 @@ impl BasicConsumer {
 
         match mapping {
             Some(mapping) => {
-                if mapping.generated_line as i32 == generated_line {
+                if (mapping.generated_line + 1) as i32 == generated_line {
+                    let is_range = self
+                        .range_mapping_positions()
+                        .contains(&(mapping.generated_line, mapping.generated_column));
+                    // Inside a range mapping, each column past its start maps
+                    // one column further into the original. A mapping found
+                    // after the column is used as is.
+                    let offset = if is_range && generated_column >= mapping.generated_column as i32
+                    {
+                        generated_column - mapping.generated_column as i32
+                    } else {
+                        0
+                    };
                     mapping.original.clone().map(|original| Mapping {
                         name: original
                             .name
+                            .filter(|_| offset == 0)
                             .map(|it| self.source_map.names.as_ref().unwrap()[it as usize].clone()),
                         source: self.absolute_sources.at(original.source as i32),
                         original: Some(Position {
                             line: (original.original_line + 1) as i32,
-                            column: original.original_column as i32,
+                            column: original.original_column as i32 + offset,
                         }),
                         generated: Position {
                             line: (mapping.generated_line + 1) as i32,
-                            column: mapping.generated_column as i32,
+                            column: mapping.generated_column as i32 + offset,
                         },
                         last_generated_column: mapping.last_generated_column.map(|it| it as i32),
                     })
@@ -342,9 +647,105 @@ impl BasicConsumer {
         }
     }
 
+    /// Whether a range mapping starts at the `generated` position: the code
+    /// from there to the next mapping is a verbatim copy of the original.
+    pub fn is_range_mapping(&mut self, generated: &Position) -> bool {
+        if generated.line < 1 || generated.column < 0 {
+            return false;
+        }
+        if self.mappings.is_none() {
+            self.mappings = self.parse_mappings().ok();
+        }
+        self.range_mapping_positions()
+            .contains(&((generated.line - 1) as u32, generated.column as u32))
+    }
+
+    /// The generated positions of the range mappings, decoded on first use.
+    /// Expects the mappings to be parsed.
+    fn range_mapping_positions(&mut self) -> &HashSet<(u32, u32)> {
+        if self.range_mappings.is_none() {
+            let positions = match (&self.source_map.range_mappings, &self.mappings) {
+                (Some(range_mappings), Some(mappings)) => range_mappings::generated_positions(
+                    range_mappings,
+                    mappings.by_generated_location(),
+                ),
+                _ => HashSet::new(),
+            };
+            self.range_mappings = Some(positions);
+        }
+        self.range_mappings.as_ref().unwrap()
+    }
+
+    /// The map's `debugId`, if it has one.
+    pub fn debug_id(&self) -> Option<&str> {
+        self.source_map.debug_id.as_deref()
+    }
+
+    /// The map's `originalScopes` and `generatedRanges`, decoded on first use.
+    /// Empty if the map has none or they are malformed.
+    pub fn scopes(&mut self) -> &Scopes {
+        if self.scopes.is_none() {
+            let source_map = &self.source_map;
+            let scopes = Scopes::decode(
+                source_map.original_scopes.as_deref().unwrap_or_default(),
+                source_map.generated_ranges.as_deref().unwrap_or_default(),
+                &self.absolute_sources.to_vec(),
+                source_map.names.as_deref().unwrap_or_default(),
+            );
+            self.scopes = Some(scopes.unwrap_or_default());
+        }
+        self.scopes.as_ref().unwrap()
+    }
+
+    /// The innermost original scope the code at `generated` comes from,
+    /// according to the map's scopes.
+    pub fn original_scope_at(&mut self, generated: Position) -> Option<&OriginalScope> {
+        self.scopes().original_scope_at(&generated)
+    }
+
+    ///
+    /// The name of the original function the code at `generated` belongs to,
+    /// according to the map's scopes. Prefer this over the name of the
+    /// mapping for stack traces: mappings only name identifiers, and often
+    /// not the enclosing function.
+    ///
+    pub fn original_function_name_at(&mut self, generated: Position) -> Option<&str> {
+        self.scopes().original_function_name_at(&generated)
+    }
+
+    ///
+    /// The name of the function enclosing `original` in `source`, according to
+    /// the Metro function map of the source in `x_facebook_sources`.
+    ///
+    pub fn function_name_for(&mut self, source: &str, original: Position) -> Option<String> {
+        let index = u32::try_from(self.find_source_index(source)?).ok()?;
+        let source_map = &self.source_map;
+        let mappings = self.function_maps.entry(index).or_insert_with(|| {
+            source_map
+                .function_map(index as usize)
+                .and_then(function_map::decode)
+                .unwrap_or_default()
+        });
+        function_map::name_for(mappings, &original).map(|it| it.to_string())
+    }
+
+    /// Whether `source` is in the map's `ignoreList` (or `x_google_ignoreList`).
+    pub fn is_ignored(&mut self, source: &str) -> bool {
+        match self.find_source_index(source) {
+            Some(index) => self
+                .source_map
+                .ignored_source_indexes()
+                .contains(&(index as u32)),
+            None => false,
+        }
+    }
+
     pub fn has_contents_of_all_sources(&self) -> bool {
         match self.source_map.sources_content {
-            Some(ref s) => s.len() >= self.source_map.sources.as_ref().unwrap().len(),
+            Some(ref s) => {
+                s.len() >= self.source_map.sources.as_ref().unwrap().len()
+                    && s.iter().all(Option::is_some)
+            }
             None => false,
         }
     }
@@ -356,12 +757,18 @@ impl BasicConsumer {
     ) -> Option<String> {
         self.source_map.sources_content.as_ref()?;
 
-        let sources_content = self.source_map.sources_content.clone().unwrap();
         let panic_on_missing = panic_on_missing.unwrap_or(true);
 
         let index = self.find_source_index(source);
-        return match index {
-            Some(i) => Some(sources_content[i as usize].clone()),
+        match index {
+            // `sourcesContent` may be shorter than `sources`.
+            Some(i) => self
+                .source_map
+                .sources_content
+                .as_ref()?
+                .get(i as usize)
+                .cloned()
+                .flatten(),
             None => {
                 if panic_on_missing {
                     panic!(r#""{}" is not in the SourceMap."#, source);
@@ -369,7 +776,7 @@ impl BasicConsumer {
                     None
                 }
             }
-        };
+        }
     }
 
     pub fn generated_position_for(
@@ -379,10 +786,7 @@ impl BasicConsumer {
         original_column: i32,
         bias: Option<source_map_mappings::Bias>,
     ) -> Option<Mapping> {
-        let source = match self.find_source_index(source) {
-            Some(s) => s,
-            None => return None,
-        };
+        let source = self.find_source_index(source)?;
 
         if original_line < 1 {
             panic!("Line numbers must be >= 1")
@@ -425,17 +829,18 @@ impl BasicConsumer {
                             column: mapping.generated_column as i32,
                         },
                         original: mapping.original.as_ref().map(|it| Position {
-                            line: it.original_line as i32,
+                            line: (it.original_line + 1) as i32,
                             column: it.original_column as i32,
                         }),
                         source: mapping
                             .original
                             .as_ref()
-                            .map(|it| self.absolute_sources.at(it.source as i32))
-                            .flatten(),
-                        name: mapping.original.as_ref().map(|it| {
-                            self.source_map.names.as_ref().unwrap()[it.source as usize].clone()
-                        }),
+                            .and_then(|it| self.absolute_sources.at(it.source as i32)),
+                        name: mapping
+                            .original
+                            .as_ref()
+                            .and_then(|it| it.name)
+                            .map(|it| self.source_map.names.as_ref().unwrap()[it as usize].clone()),
                         last_generated_column: last_column,
                     })
                 } else {
@@ -445,6 +850,450 @@ impl BasicConsumer {
             None => None,
         }
     }
+
+    /// Line table over the `sourcesContent` entry of the source at `index`, if
+    /// the map carries one.
+    fn original_line_index(&mut self, index: u32) -> Option<Arc<LineIndex>> {
+        if !self.source_line_indexes.contains_key(&index) {
+            let content = self
+                .source_map
+                .sources_content
+                .as_ref()?
+                .get(index as usize)?
+                .as_ref()?;
+            let line_index = Arc::new(LineIndex::new(content));
+            self.source_line_indexes.insert(index, line_index);
+        }
+        self.source_line_indexes.get(&index).cloned()
+    }
+
+    /// Build (and cache) a `LineIndex` over the original source from
+    /// `sourcesContent`, so offsets into it can be resolved without having the
+    /// file on disk.
+    pub fn source_line_index(&mut self, source: &str) -> Option<Arc<LineIndex>> {
+        let index = self.find_source_index(source)?;
+        self.original_line_index(index as u32)
+    }
+
+    fn convert_original_column(
+        &mut self,
+        source: &str,
+        original: &mut Position,
+        from: ColumnUnit,
+        to: ColumnUnit,
+    ) {
+        let index = match self.find_source_index(source) {
+            Some(index) => index as u32,
+            None => return,
+        };
+        if let Some(line_index) = self.original_line_index(index) {
+            if let Some(column) =
+                line_index.convert_column(original.line, original.column, from, to)
+            {
+                original.column = column;
+            }
+        }
+    }
+
+    ///
+    /// Same as `original_position_for`, but `generated.column` is counted in
+    /// `unit` over `generated_text` rather than in UTF-16 code units. Columns of
+    /// the returned mapping are in `unit` too; the original column can only be
+    /// converted when the map has `sourcesContent` for its source, otherwise it
+    /// is left in UTF-16 code units.
+    ///
+    pub fn original_position_for_with_unit(
+        &mut self,
+        generated: Position,
+        unit: ColumnUnit,
+        generated_text: &LineIndex,
+        bias: Option<source_map_mappings::Bias>,
+    ) -> Option<Mapping> {
+        let generated = generated_text.convert_position(&generated, unit, ColumnUnit::Utf16)?;
+        let mut mapping = self.original_position_for(generated, bias)?;
+
+        let line = mapping.generated.line;
+        mapping.generated = generated_text
+            .convert_position(&mapping.generated, ColumnUnit::Utf16, unit)
+            .unwrap_or(mapping.generated);
+        mapping.last_generated_column = mapping.last_generated_column.map(|it| {
+            generated_text
+                .convert_column(line, it, ColumnUnit::Utf16, unit)
+                .unwrap_or(it)
+        });
+
+        if let (Some(source), Some(mut original)) =
+            (mapping.source.clone(), mapping.original.take())
+        {
+            self.convert_original_column(&source, &mut original, ColumnUnit::Utf16, unit);
+            mapping.original = Some(original);
+        }
+
+        Some(mapping)
+    }
+
+    ///
+    /// Same as `generated_position_for`, but `original_column` and the columns of
+    /// the returned mapping are counted in `unit`. The original side is
+    /// converted through `sourcesContent` when the map has it, the generated
+    /// side through `generated_text`.
+    ///
+    pub fn generated_position_for_with_unit(
+        &mut self,
+        source: &str,
+        original_line: i32,
+        original_column: i32,
+        unit: ColumnUnit,
+        generated_text: &LineIndex,
+        bias: Option<source_map_mappings::Bias>,
+    ) -> Option<Mapping> {
+        let mut original = Position {
+            line: original_line,
+            column: original_column,
+        };
+        self.convert_original_column(source, &mut original, unit, ColumnUnit::Utf16);
+
+        let mut mapping =
+            self.generated_position_for(source, original.line, original.column, bias)?;
+
+        let line = mapping.generated.line;
+        mapping.generated = generated_text
+            .convert_position(&mapping.generated, ColumnUnit::Utf16, unit)
+            .unwrap_or(mapping.generated);
+        mapping.last_generated_column = mapping.last_generated_column.map(|it| {
+            if it < 0 {
+                it
+            } else {
+                generated_text
+                    .convert_column(line, it, ColumnUnit::Utf16, unit)
+                    .unwrap_or(it)
+            }
+        });
+
+        if let (Some(source), Some(mut original)) =
+            (mapping.source.clone(), mapping.original.take())
+        {
+            self.convert_original_column(&source, &mut original, ColumnUnit::Utf16, unit);
+            mapping.original = Some(original);
+        }
+
+        Some(mapping)
+    }
+
+    ///
+    /// Same as `original_position_for`, but the generated location is a byte
+    /// offset into `generated_text`. The returned mapping uses regular
+    /// positions; see `original_offset_for` to get an offset back.
+    ///
+    pub fn original_position_for_offset(
+        &mut self,
+        generated_offset: usize,
+        generated_text: &LineIndex,
+        bias: Option<source_map_mappings::Bias>,
+    ) -> Option<Mapping> {
+        let generated = generated_text.offset_to_position(generated_offset)?;
+        self.original_position_for(generated, bias)
+    }
+
+    ///
+    /// Same as `original_position_for`, for the map of a WebAssembly module:
+    /// such maps have a single generated line and use byte offsets as
+    /// columns. `offset` must be counted the way the map counts them, from
+    /// the start of the module or of the code section; see
+    /// `wasm::code_section_range` to convert between the two.
+    ///
+    pub fn original_position_for_wasm_offset(&mut self, offset: u32) -> Option<Mapping> {
+        self.original_position_for(
+            Position {
+                line: 1,
+                column: i32::try_from(offset).ok()?,
+            },
+            None,
+        )
+    }
+
+    ///
+    /// Resolve a byte offset into `generated_text` to the original source and a
+    /// byte offset into it. The original source text comes from
+    /// `sourcesContent`, so this returns `None` for sources without content.
+    ///
+    pub fn original_offset_for(
+        &mut self,
+        generated_offset: usize,
+        generated_text: &LineIndex,
+        bias: Option<source_map_mappings::Bias>,
+    ) -> Option<(String, usize)> {
+        let mapping = self.original_position_for_offset(generated_offset, generated_text, bias)?;
+        let source = mapping.source?;
+        let original_text = self.source_line_index(&source)?;
+        let offset = original_text.position_to_offset(mapping.original.as_ref()?)?;
+        Some((source, offset))
+    }
+
+    ///
+    /// Same as `generated_position_for`, but the original location is a byte
+    /// offset into the original source. When `original_text` is `None` the
+    /// source text is taken from `sourcesContent`.
+    ///
+    pub fn generated_position_for_offset(
+        &mut self,
+        source: &str,
+        original_offset: usize,
+        original_text: Option<&LineIndex>,
+        bias: Option<source_map_mappings::Bias>,
+    ) -> Option<Mapping> {
+        let original = match original_text {
+            Some(original_text) => original_text.offset_to_position(original_offset)?,
+            None => self
+                .source_line_index(source)?
+                .offset_to_position(original_offset)?,
+        };
+        self.generated_position_for(source, original.line, original.column, bias)
+    }
+
+    ///
+    /// Resolve `generated` to its original location and render the original
+    /// source around it, taken from `sourcesContent`. Once
+    /// `compute_column_spans` has run the marker underlines the whole span of
+    /// the mapping instead of a single column.
+    ///
+    pub fn code_frame(
+        &mut self,
+        generated: Position,
+        options: &CodeFrameOptions,
+    ) -> Option<String> {
+        let mapping = self.original_position_for(generated, None)?;
+        let original = mapping.original.clone()?;
+        let content = self.source_content_for(mapping.source.as_deref()?, Some(false))?;
+
+        let end_column = mapping
+            .last_generated_column
+            .map(|last| original.column + (last - mapping.generated.column));
+
+        Some(code_frame::render(
+            &content,
+            original.line,
+            original.column,
+            end_column,
+            options,
+        ))
+    }
+
+    /// Convert a parsed mapping.
+    fn to_mapping(&self, mapping: &source_map_mappings::Mapping) -> Mapping {
+        Mapping {
+            generated: Position {
+                line: (mapping.generated_line + 1) as i32,
+                column: mapping.generated_column as i32,
+            },
+            original: mapping.original.as_ref().map(|it| Position {
+                line: (it.original_line + 1) as i32,
+                column: it.original_column as i32,
+            }),
+            source: mapping
+                .original
+                .as_ref()
+                .and_then(|it| self.absolute_sources.at(it.source as i32)),
+            name: mapping
+                .original
+                .as_ref()
+                .and_then(|it| it.name)
+                .and_then(|it| self.source_map.names.as_ref()?.get(it as usize).cloned()),
+            last_generated_column: mapping.last_generated_column.map(|it| it as i32),
+        }
+    }
+
+    ///
+    /// Every mapping whose generated span overlaps the half-open range
+    /// `start..end`, in generated order. Column spans are computed first, so
+    /// `last_generated_column` is set on every mapping but the last one of its
+    /// line, which runs to the end of the line and has `None`. Unmapped
+    /// segments are included with `original` and `source` set to `None`.
+    ///
+    pub fn mappings_in_generated_range(&mut self, start: Position, end: Position) -> Vec<Mapping> {
+        self.compute_column_spans();
+        let mappings = match self.mappings.as_ref() {
+            Some(mappings) => mappings.by_generated_location(),
+            None => return vec![],
+        };
+
+        let first_line = (start.line - 1).max(0) as u32;
+        let first = mappings.partition_point(|it| it.generated_line < first_line);
+
+        mappings[first..]
+            .iter()
+            .take_while(|it| (it.generated_line + 1) as i32 <= end.line)
+            .filter(|it| {
+                span_overlaps(
+                    (it.generated_line + 1) as i32,
+                    it.generated_column as i32,
+                    it.last_generated_column.map(|it| it as i32),
+                    &start,
+                    &end,
+                )
+            })
+            .map(|it| self.to_mapping(it))
+            .collect()
+    }
+
+    ///
+    /// Every mapping whose original span in `source` overlaps the half-open
+    /// range `start..end`, in original order. A mapping's original span runs to
+    /// the next mapped column of the same source on the same line, or to the end
+    /// of that line.
+    ///
+    pub fn mappings_in_original_range(
+        &mut self,
+        source: &str,
+        start: Position,
+        end: Position,
+    ) -> Vec<Mapping> {
+        let source = match self.find_source_index(source) {
+            Some(source) => source as u32,
+            None => return vec![],
+        };
+
+        self.compute_column_spans();
+        // Taken out for the loop, so that the bucket can be borrowed while
+        // `to_mapping` borrows the rest of `self`.
+        let mut all_mappings = match self.mappings.take() {
+            Some(mappings) => mappings,
+            None => return vec![],
+        };
+        let mappings = all_mappings.by_original_source(source);
+
+        let original_of = |mapping: &source_map_mappings::Mapping| {
+            let original = mapping.original.as_ref().unwrap();
+            (
+                (original.original_line + 1) as i32,
+                original.original_column as i32,
+            )
+        };
+
+        let first_line = (start.line - 1).max(0) as u32;
+        let first =
+            mappings.partition_point(|it| it.original.as_ref().unwrap().original_line < first_line);
+
+        let mut result = vec![];
+        for (index, mapping) in mappings.iter().enumerate().skip(first) {
+            let (line, column) = original_of(mapping);
+            if line > end.line {
+                break;
+            }
+            // The bucket is sorted by original position, so the span ends at
+            // the first mapping after every one at this position.
+            let rest = &mappings[index + 1..];
+            let end_column = rest
+                .get(rest.partition_point(|it| original_of(it) <= (line, column)))
+                .map(original_of)
+                .filter(|(next_line, _)| *next_line == line)
+                .map(|(_, next_column)| next_column);
+
+            if span_overlaps(line, column, end_column, &start, &end) {
+                result.push(self.to_mapping(mapping));
+            }
+        }
+
+        self.mappings = Some(all_mappings);
+        result
+    }
+
+    ///
+    /// Build a generator from every mapping of this map, rewritten or dropped
+    /// by `f`. Sources are written out resolved against `sourceRoot`, with the
+    /// `sourcesContent` of the ones still in use.
+    ///
+    fn rewrite_mappings(&mut self, f: impl Fn(Mapping) -> Option<Mapping>) -> SourceMapGenerator {
+        let mappings = self.mappings_in_generated_range(
+            Position { line: 1, column: 0 },
+            Position {
+                line: i32::MAX,
+                column: 0,
+            },
+        );
+
+        let mut generator = SourceMapGenerator::new(self.source_map.file.clone(), None, true);
+        for mapping in mappings {
+            let is_range = self.is_range_mapping(&mapping.generated);
+            let mut mapping = match f(mapping) {
+                Some(mapping) => mapping,
+                None => continue,
+            };
+            mapping.last_generated_column = None;
+            if let Some(source) = &mapping.source {
+                if !generator.sources.has(source.clone()) {
+                    let content = self.source_content_for(source, Some(false));
+                    generator.set_source_content(source.clone(), content);
+                }
+            }
+            if is_range {
+                generator.add_range_mapping(mapping);
+            } else {
+                generator.add_mapping(mapping);
+            }
+        }
+        generator
+    }
+
+    ///
+    /// Move every generated position down by `lines` lines. Mappings on the
+    /// first generated line also move right by `columns`, as they do when
+    /// text without a trailing newline is put in front of the code. Negative
+    /// shifts cut code off the front: mappings that would land before line 1,
+    /// column 0 are dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use rusty_source_map::consumer::BasicConsumer;
+    ///
+    /// let mut consumer = BasicConsumer::new(
+    ///     r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA;AACA"}"#,
+    ///     None,
+    /// );
+    /// let mut generator = consumer.shift(1, 4);
+    /// assert!(generator.as_string().contains(r#""mappings":";IAAA;AACA""#));
+    /// ```
+    ///
+    pub fn shift(&mut self, lines: i32, columns: i32) -> SourceMapGenerator {
+        self.rewrite_mappings(|mut mapping| {
+            if mapping.generated.line == 1 {
+                mapping.generated.column = mapping.generated.column.checked_add(columns)?;
+            }
+            mapping.generated.line = mapping.generated.line.checked_add(lines)?;
+            if mapping.generated.line < 1 || mapping.generated.column < 0 {
+                return None;
+            }
+            Some(mapping)
+        })
+    }
+
+    /// The map of `text` followed by the code this map belongs to.
+    pub fn prepend(&mut self, text: &str) -> SourceMapGenerator {
+        let index = LineIndex::new(text);
+        let last_line = index.line_count() as i32;
+        let columns = index
+            .line(last_line)
+            .map(|it| it.encode_utf16().count() as i32)
+            .unwrap_or(0);
+        self.shift(last_line - 1, columns)
+    }
+
+    ///
+    /// A standalone map for generated lines `start_line..end_line` (1-based,
+    /// end exclusive), as if those lines were a file of their own. A
+    /// `start_line` below 1 counts as 1, and an empty or inverted range gives a
+    /// map without mappings.
+    ///
+    pub fn slice(&mut self, start_line: i32, end_line: i32) -> SourceMapGenerator {
+        let start_line = start_line.max(1);
+        self.rewrite_mappings(|mut mapping| {
+            if mapping.generated.line < start_line || mapping.generated.line >= end_line {
+                return None;
+            }
+            mapping.generated.line -= start_line - 1;
+            Some(mapping)
+        })
+    }
 }
 
 impl ConsumerTrait for BasicConsumer {
@@ -496,7 +1345,8 @@ impl IndexedConsumer {
         let version = source_map.version;
 
         // Once again, Sass deviates from the spec and supplies the version as a
-        // string rather than a number, so we use loose equality checking here.
+        // string rather than a number. Parse with `ParseOptions::lenient` to
+        // accept it.
         if version != SUPPORTED_SOURCE_MAP_VERSION {
             panic!("Unsupported version: {}", version);
         }
@@ -534,7 +1384,6 @@ impl IndexedConsumer {
                     .unwrap()
                     .par_iter()
                     .map({
-                        let last_offset = last_offset;
                         move |section| {
                             if section.url.is_some() {
                                 panic!("Section with url is not supported.");
@@ -572,32 +1421,14 @@ impl IndexedConsumer {
         }
     }
 
-    /// The list of original sources.
+    /// The list of original sources of every section, resolved like
+    /// `BasicConsumer::get_sources` resolves them.
     pub fn get_sources(&self) -> Vec<String> {
-        let mut sources: Vec<String> = vec![];
-
-        for i in 0..(*self.sections).borrow().len() {
-            for j in 0..(*self.sections).borrow()[i]
-                .consumer
-                .source_map
-                .sources
-                .as_ref()
-                .unwrap()
-                .len()
-            {
-                sources.push(
-                    (*self.sections).borrow()[i]
-                        .consumer
-                        .source_map
-                        .sources
-                        .as_ref()
-                        .unwrap()[j]
-                        .clone(),
-                );
-            }
-        }
-
-        sources
+        (*self.sections)
+            .borrow()
+            .iter()
+            .flat_map(|section| section.consumer.get_sources())
+            .collect()
     }
 
     pub fn original_position_for(
@@ -627,10 +1458,10 @@ impl IndexedConsumer {
             None,
         );
 
-        (&*self.sections)
+        (*self.sections)
             .borrow_mut()
             .get_mut(section_index as usize)
-            .map(|it| {
+            .and_then(|it| {
                 it.consumer.original_position_for(
                     Position {
                         line: needle.line - (it.generated_offset.line - 1),
@@ -644,7 +1475,32 @@ impl IndexedConsumer {
                     bias,
                 )
             })
-            .flatten()
+    }
+
+    /// The `debugId` of the index map itself; sections may have their own.
+    pub fn debug_id(&self) -> Option<&str> {
+        self.source_map.debug_id.as_deref()
+    }
+
+    /// Whether `source` is ignored by the `ignoreList` of the section it comes
+    /// from.
+    pub fn is_ignored(&mut self, source: &str) -> bool {
+        for section in (*self.sections).borrow_mut().iter_mut() {
+            if section.consumer.find_source_index(source).is_some() {
+                return section.consumer.is_ignored(source);
+            }
+        }
+        false
+    }
+
+    /// See `BasicConsumer::function_name_for`.
+    pub fn function_name_for(&mut self, source: &str, original: Position) -> Option<String> {
+        for section in (*self.sections).borrow_mut().iter_mut() {
+            if section.consumer.find_source_index(source).is_some() {
+                return section.consumer.function_name_for(source, original);
+            }
+        }
+        None
     }
 
     /// Return true if we have the source content for every source in the source
@@ -772,7 +1628,6 @@ impl IndexedConsumer {
                             mapping.generated_column += column_shift;
                             if let Some(g) = &mut mapping.last_generated_column {
                                 *g += column_shift;
-                            } else {
                             }
                         }
 
@@ -809,6 +1664,133 @@ impl IndexedConsumer {
             ele.consumer.compute_column_spans()
         }
     }
+
+    ///
+    /// Every mapping whose generated span overlaps the half-open range
+    /// `start..end`, across all sections and in generated order. See
+    /// `BasicConsumer::mappings_in_generated_range`; spans are clipped to the
+    /// start of the next section.
+    ///
+    pub fn mappings_in_generated_range(&mut self, start: Position, end: Position) -> Vec<Mapping> {
+        let mut sections = (*self.sections).borrow_mut();
+        let offsets: Vec<Position> = sections
+            .iter()
+            .map(|it| Position {
+                line: it.generated_offset.line,
+                column: it.generated_offset.column - 1,
+            })
+            .collect();
+
+        let mut result = vec![];
+        for (index, section) in sections.iter_mut().enumerate() {
+            let section_start = &offsets[index];
+            let section_end = offsets.get(index + 1);
+
+            match position_cmp(section_start, &end) {
+                std::cmp::Ordering::Greater => break,
+                std::cmp::Ordering::Equal if position_cmp(&start, &end).is_ne() => break,
+                _ => {}
+            }
+            if let Some(section_end) = section_end {
+                if position_cmp(section_end, &start).is_le() {
+                    continue;
+                }
+            }
+
+            let local_start = section_local(section_start, &start);
+            let local_end = match section_end {
+                Some(section_end) if position_cmp(section_end, &end).is_lt() => {
+                    section_local(section_start, section_end)
+                }
+                _ => section_local(section_start, &end),
+            };
+
+            for mut mapping in section
+                .consumer
+                .mappings_in_generated_range(local_start, local_end)
+            {
+                shift_to_section(section_start, section_end, &mut mapping);
+                result.push(mapping);
+            }
+        }
+
+        result
+    }
+
+    ///
+    /// Every mapping whose original span in `source` overlaps the half-open
+    /// range `start..end`, collected from every section that contains `source`.
+    ///
+    pub fn mappings_in_original_range(
+        &mut self,
+        source: &str,
+        start: Position,
+        end: Position,
+    ) -> Vec<Mapping> {
+        let mut sections = (*self.sections).borrow_mut();
+        let offsets: Vec<Position> = sections
+            .iter()
+            .map(|it| Position {
+                line: it.generated_offset.line,
+                column: it.generated_offset.column - 1,
+            })
+            .collect();
+
+        let mut result = vec![];
+        for (index, section) in sections.iter_mut().enumerate() {
+            for mut mapping in
+                section
+                    .consumer
+                    .mappings_in_original_range(source, start.clone(), end.clone())
+            {
+                shift_to_section(&offsets[index], offsets.get(index + 1), &mut mapping);
+                result.push(mapping);
+            }
+        }
+
+        result
+    }
+}
+
+/// Translate a generated position into the coordinates of the section that
+/// starts at `section_start` (1-based line, 0-based column).
+fn section_local(section_start: &Position, position: &Position) -> Position {
+    if position_cmp(position, section_start).is_le() {
+        return Position { line: 1, column: 0 };
+    }
+    let line = position.line - section_start.line + 1;
+    let column = if line == 1 {
+        position.column - section_start.column
+    } else {
+        position.column
+    };
+    Position { line, column }
+}
+
+/// Move a mapping from section coordinates back to the whole file, clipping its
+/// span at the start of the next section.
+fn shift_to_section(
+    section_start: &Position,
+    section_end: Option<&Position>,
+    mapping: &mut Mapping,
+) {
+    if mapping.generated.line == 1 {
+        mapping.generated.column += section_start.column;
+        if let Some(last) = mapping.last_generated_column.as_mut() {
+            *last += section_start.column;
+        }
+    }
+    mapping.generated.line += section_start.line - 1;
+
+    if let Some(section_end) = section_end {
+        if mapping.generated.line == section_end.line {
+            let last = mapping
+                .last_generated_column
+                .unwrap_or(section_end.column)
+                .min(section_end.column);
+            mapping.last_generated_column = Some(last);
+        }
+    }
 }
 
 impl ConsumerTrait for IndexedConsumer {
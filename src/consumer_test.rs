@@ -1,4 +1,3 @@
-#![allow(unused_imports)]
 use crate::constants::*;
 use crate::consumer::*;
 
@@ -37,11 +36,53 @@ fn test_is_consumer() {
 #[test]
 fn test_no_section() {
     let map = create_consumer(testMap, None).unwrap();
-    if let Consumer::BasicConsumer(_) = map {
-        assert!(true)
-    } else {
-        assert!(false)
-    }
+    assert!(matches!(map, Consumer::BasicConsumer(_)));
+}
+
+#[test]
+fn test_basic_consumer_lookups_use_one_based_lines() {
+    use crate::source_map::Position;
+
+    // 1:0 maps to a.js 1:0 with the name `x`, 1:2 to b.js 2:1 with no name.
+    let mut map = BasicConsumer::new(
+        r#"{"version":3,"sources":["a.js","b.js"],"names":["x"],"mappings":"AAAAA,ECCC"}"#,
+        None,
+    );
+
+    let mapping = map
+        .original_position_for(Position { line: 1, column: 0 }, None)
+        .unwrap();
+    assert_eq!(mapping.source.as_deref(), Some("a.js"));
+    assert_eq!(
+        mapping.original.map(|it| (it.line, it.column)),
+        Some((1, 0))
+    );
+    assert_eq!(mapping.name.as_deref(), Some("x"));
+
+    let mapping = map
+        .original_position_for(Position { line: 1, column: 3 }, None)
+        .unwrap();
+    assert_eq!(mapping.source.as_deref(), Some("b.js"));
+    assert_eq!(
+        mapping.original.map(|it| (it.line, it.column)),
+        Some((2, 1))
+    );
+    assert_eq!(mapping.name, None);
+
+    let mapping = map.generated_position_for("b.js", 2, 1, None).unwrap();
+    assert_eq!((mapping.generated.line, mapping.generated.column), (1, 2));
+    assert_eq!(
+        mapping.original.map(|it| (it.line, it.column)),
+        Some((2, 1))
+    );
+    assert_eq!(mapping.name, None);
+
+    let mapping = map.generated_position_for("a.js", 1, 0, None).unwrap();
+    assert_eq!(
+        mapping.original.map(|it| (it.line, it.column)),
+        Some((1, 0))
+    );
+    assert_eq!(mapping.name.as_deref(), Some("x"));
 }
 
 // exports[
@@ -122,3 +163,893 @@ fn test_sources() {
     }
     panic!("Not ok");
 }
+
+// exports["test mapping tokens back exactly"] = async function(assert) {
+//   const map = await new SourceMapConsumer(util.testMap);
+//
+//   util.assertMapping(1, 1, "/the/root/one.js", 1, 1, null, null, map, assert);
+//   util.assertMapping(1, 5, "/the/root/one.js", 1, 5, null, null, map, assert);
+//   util.assertMapping(1, 9, "/the/root/one.js", 1, 11, null, null, map, assert);
+//   util.assertMapping(1, 18, "/the/root/one.js", 1, 21, "bar", null, map, assert);
+//   util.assertMapping(1, 21, "/the/root/one.js", 2, 3, null, null, map, assert);
+//   util.assertMapping(1, 28, "/the/root/one.js", 2, 10, "baz", null, map, assert);
+//   util.assertMapping(1, 32, "/the/root/one.js", 2, 14, "bar", null, map, assert);
+//
+//   util.assertMapping(2, 1, "/the/root/two.js", 1, 1, null, null, map, assert);
+//   util.assertMapping(2, 5, "/the/root/two.js", 1, 5, null, null, map, assert);
+//   util.assertMapping(2, 9, "/the/root/two.js", 1, 11, null, null, map, assert);
+//   util.assertMapping(2, 18, "/the/root/two.js", 1, 21, "n", null, map, assert);
+//   util.assertMapping(2, 21, "/the/root/two.js", 2, 3, null, null, map, assert);
+//   util.assertMapping(2, 28, "/the/root/two.js", 2, 10, "n", null, map, assert);
+//
+//   map.destroy();
+// };
+#[test]
+fn test_mapping_tokens_back_exactly() {
+    use crate::source_map::Position;
+
+    let mut map = create_consumer(testMap, None).unwrap();
+    let map = map.as_basic_consumer_mut();
+
+    let cases = [
+        (1, 1, "/the/root/one.js", 1, 1, None),
+        (1, 5, "/the/root/one.js", 1, 5, None),
+        (1, 9, "/the/root/one.js", 1, 11, None),
+        (1, 18, "/the/root/one.js", 1, 21, Some("bar")),
+        (1, 21, "/the/root/one.js", 2, 3, None),
+        (1, 28, "/the/root/one.js", 2, 10, Some("baz")),
+        (1, 32, "/the/root/one.js", 2, 14, Some("bar")),
+        (2, 1, "/the/root/two.js", 1, 1, None),
+        (2, 5, "/the/root/two.js", 1, 5, None),
+        (2, 9, "/the/root/two.js", 1, 11, None),
+        (2, 18, "/the/root/two.js", 1, 21, Some("n")),
+        (2, 21, "/the/root/two.js", 2, 3, None),
+        (2, 28, "/the/root/two.js", 2, 10, Some("n")),
+    ];
+
+    for (line, column, source, original_line, original_column, name) in cases {
+        let mapping = map
+            .original_position_for(Position { line, column }, None)
+            .unwrap();
+        assert_eq!(mapping.source.as_deref(), Some(source));
+        let original = mapping.original.unwrap();
+        assert_eq!(
+            (original.line, original.column),
+            (original_line, original_column)
+        );
+        assert_eq!(mapping.name.as_deref(), name);
+
+        let mapping = map
+            .generated_position_for(source, original_line, original_column, None)
+            .unwrap();
+        assert_eq!(
+            (mapping.generated.line, mapping.generated.column),
+            (line, column)
+        );
+        assert_eq!(mapping.name.as_deref(), name);
+    }
+}
+
+#[test]
+fn test_positions_in_other_column_units() {
+    use crate::generator::SourceMapGenerator;
+    use crate::line_index::{ColumnUnit, LineIndex};
+    use crate::mapping::Mapping;
+    use crate::source_map::Position;
+
+    let original = "const é = \"😀\"; foo();";
+    let generated = LineIndex::new("const é=\"😀\";foo();");
+
+    let mut generator = SourceMapGenerator::new(None, None, false);
+    generator.set_source_content("a.js".to_string(), Some(original.to_string()));
+    let mapping = |generated_line, original_line| Mapping {
+        generated: Position {
+            line: generated_line,
+            column: 16,
+        },
+        original: Some(Position {
+            line: original_line,
+            column: 19,
+        }),
+        source: Some("a.js".to_string()),
+        name: Some("foo".to_string()),
+        last_generated_column: None,
+    };
+    let original_text = LineIndex::new(original);
+    assert!(generator.add_mapping_with_unit(
+        mapping(1, 1),
+        ColumnUnit::Utf8,
+        &generated,
+        Some(&original_text),
+    ));
+    // Lines past the end of either text can't be converted.
+    assert!(!generator.add_mapping_with_unit(
+        mapping(2, 1),
+        ColumnUnit::Utf8,
+        &generated,
+        Some(&original_text),
+    ));
+    assert!(!generator.add_mapping_with_unit(
+        mapping(1, 2),
+        ColumnUnit::Utf8,
+        &generated,
+        Some(&original_text),
+    ));
+
+    let mut map = BasicConsumer::from_source_map(&mut generator, None);
+
+    // Plain lookups stay in UTF-16 code units.
+    let mapping = map
+        .original_position_for(
+            Position {
+                line: 1,
+                column: 13,
+            },
+            None,
+        )
+        .unwrap();
+    assert_eq!(mapping.original.unwrap().column, 16);
+
+    let mapping = map
+        .original_position_for_with_unit(
+            Position {
+                line: 1,
+                column: 16,
+            },
+            ColumnUnit::Utf8,
+            &generated,
+            None,
+        )
+        .unwrap();
+    assert_eq!(mapping.generated.column, 16);
+    assert_eq!(mapping.original.unwrap().column, 19);
+
+    let mapping = map
+        .generated_position_for_with_unit("a.js", 1, 16, ColumnUnit::CodePoint, &generated, None)
+        .unwrap();
+    assert_eq!(mapping.generated.column, 12);
+    assert_eq!(mapping.original.unwrap().column, 15);
+}
+
+#[test]
+fn test_offset_lookups() {
+    use crate::generator::SourceMapGenerator;
+    use crate::line_index::LineIndex;
+
+    let original = "function foo() {\n  return \"é\" + bar;\n}\n";
+    let generated = "function foo(){return\"é\"+bar}";
+    let original_text = LineIndex::new(original);
+    let generated_text = LineIndex::new(generated);
+
+    let mut generator = SourceMapGenerator::new(None, None, false);
+    generator.set_source_content("a.js".to_string(), Some(original.to_string()));
+    for (generated_token, original_token) in [("return", "return"), ("+", "+"), ("bar", "bar")] {
+        assert!(generator.add_mapping_at_offsets(
+            generated.find(generated_token).unwrap(),
+            &generated_text,
+            "a.js",
+            original.find(original_token).unwrap(),
+            &original_text,
+            None,
+        ));
+    }
+    assert!(!generator.add_mapping_at_offsets(
+        generated.len() + 1,
+        &generated_text,
+        "a.js",
+        0,
+        &original_text,
+        None,
+    ));
+    assert!(!generator.add_mapping_at_offsets(
+        0,
+        &generated_text,
+        "a.js",
+        original.len() + 1,
+        &original_text,
+        None,
+    ));
+
+    let mut map = BasicConsumer::from_source_map(&mut generator, None);
+
+    let bar = generated.find("bar").unwrap();
+    let mapping = map
+        .original_position_for_offset(bar, &generated_text, None)
+        .unwrap();
+    let original_position = mapping.original.unwrap();
+    assert_eq!((original_position.line, original_position.column), (2, 15));
+
+    assert_eq!(
+        map.original_offset_for(bar, &generated_text, None),
+        Some(("a.js".to_string(), original.find("bar").unwrap()))
+    );
+
+    let mapping = map
+        .generated_position_for_offset("a.js", original.find("+").unwrap(), None, None)
+        .unwrap();
+    assert_eq!(
+        generated_text.position_to_offset(&mapping.generated),
+        generated.find("+")
+    );
+}
+
+#[test]
+fn test_code_frame() {
+    use crate::code_frame::CodeFrameOptions;
+    use crate::generator::SourceMapGenerator;
+    use crate::mapping::Mapping;
+    use crate::source_map::Position;
+
+    let mut generator = SourceMapGenerator::new(None, None, false);
+    generator.set_source_content(
+        "a.js".to_string(),
+        Some("function foo() {\n  return bar;\n}\n".to_string()),
+    );
+    for (generated, original) in [(15, 2), (22, 9), (25, 12)] {
+        generator.add_mapping(Mapping {
+            generated: Position {
+                line: 1,
+                column: generated,
+            },
+            original: Some(Position {
+                line: 2,
+                column: original,
+            }),
+            source: Some("a.js".to_string()),
+            name: None,
+            last_generated_column: None,
+        });
+    }
+    let mut map = BasicConsumer::from_source_map(&mut generator, None);
+    let options = CodeFrameOptions {
+        context_lines: 1,
+        color: false,
+    };
+
+    assert_eq!(
+        map.code_frame(
+            Position {
+                line: 1,
+                column: 23
+            },
+            &options
+        )
+        .unwrap(),
+        "  1 | function foo() {\n> 2 |   return bar;\n    |          ^\n  3 | }\n"
+    );
+
+    map.compute_column_spans();
+    assert_eq!(
+        map.code_frame(
+            Position {
+                line: 1,
+                column: 23
+            },
+            &options
+        )
+        .unwrap(),
+        "  1 | function foo() {\n> 2 |   return bar;\n    |          ^^^\n  3 | }\n"
+    );
+
+    assert!(map
+        .code_frame(Position { line: 1, column: 0 }, &options)
+        .is_none());
+}
+
+#[test]
+fn test_mappings_in_generated_range() {
+    use crate::source_map::Position;
+
+    let mut map = create_consumer(testMap, None).unwrap();
+    let map = map.as_basic_consumer_mut();
+
+    let columns = |mappings: Vec<crate::mapping::Mapping>| -> Vec<(i32, i32, Option<i32>)> {
+        mappings
+            .iter()
+            .map(|it| {
+                (
+                    it.generated.line,
+                    it.generated.column,
+                    it.last_generated_column,
+                )
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        columns(map.mappings_in_generated_range(
+            Position { line: 1, column: 6 },
+            Position {
+                line: 1,
+                column: 20
+            }
+        )),
+        vec![(1, 5, Some(9)), (1, 9, Some(18)), (1, 18, Some(21))]
+    );
+    assert_eq!(
+        columns(map.mappings_in_generated_range(
+            Position {
+                line: 1,
+                column: 30
+            },
+            Position { line: 2, column: 2 }
+        )),
+        vec![(1, 28, Some(32)), (1, 32, None), (2, 1, Some(5))]
+    );
+    assert_eq!(
+        columns(map.mappings_in_generated_range(
+            Position { line: 1, column: 7 },
+            Position { line: 1, column: 7 }
+        )),
+        vec![(1, 5, Some(9))]
+    );
+    assert!(map
+        .mappings_in_generated_range(
+            Position { line: 1, column: 0 },
+            Position { line: 1, column: 1 }
+        )
+        .is_empty());
+}
+
+#[test]
+fn test_mappings_in_original_range() {
+    use crate::source_map::Position;
+
+    let mut map = create_consumer(testMap, None).unwrap();
+    let map = map.as_basic_consumer_mut();
+
+    let mappings = map.mappings_in_original_range(
+        "/the/root/one.js",
+        Position { line: 2, column: 0 },
+        Position {
+            line: 2,
+            column: 11,
+        },
+    );
+    let found: Vec<_> = mappings
+        .iter()
+        .map(|it| {
+            let original = it.original.as_ref().unwrap();
+            (
+                original.line,
+                original.column,
+                it.generated.column,
+                it.name.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        vec![(2, 3, 21, None), (2, 10, 28, Some("baz".to_string()))]
+    );
+
+    assert!(map
+        .mappings_in_original_range(
+            "/the/root/missing.js",
+            Position { line: 1, column: 0 },
+            Position { line: 9, column: 0 }
+        )
+        .is_empty());
+}
+
+#[test]
+fn test_mappings_in_original_range_at_one_position() {
+    use crate::generator::SourceMapGenerator;
+    use crate::mapping::Mapping;
+    use crate::source_map::Position;
+
+    // Generated columns 0 and 10 both come from a.js 1:0, 20 from 1:5.
+    let mut generator = SourceMapGenerator::new(None, None, false);
+    for (generated, original) in [(0, 0), (10, 0), (20, 5)] {
+        generator.add_mapping(Mapping {
+            generated: Position {
+                line: 1,
+                column: generated,
+            },
+            original: Some(Position {
+                line: 1,
+                column: original,
+            }),
+            source: Some("a.js".to_string()),
+            name: None,
+            last_generated_column: None,
+        });
+    }
+    let mut map = BasicConsumer::from_source_map(&mut generator, None);
+
+    // Both spans from 1:0 run to 1:5.
+    for _ in 0..2 {
+        let found: Vec<_> = map
+            .mappings_in_original_range(
+                "a.js",
+                Position { line: 1, column: 4 },
+                Position { line: 1, column: 5 },
+            )
+            .iter()
+            .map(|it| it.generated.column)
+            .collect();
+        assert_eq!(found, vec![0, 10]);
+    }
+}
+
+#[test]
+fn test_indexed_mappings_in_range() {
+    use crate::source_map::Position;
+
+    let mut map = create_consumer(indexedTestMap, None).unwrap();
+    let map = map.as_indexed_consumer_mut();
+
+    let mappings = map.mappings_in_generated_range(
+        Position {
+            line: 1,
+            column: 30,
+        },
+        Position { line: 2, column: 6 },
+    );
+    let found: Vec<_> = mappings
+        .iter()
+        .map(|it| {
+            (
+                it.source.clone().unwrap(),
+                it.generated.line,
+                it.generated.column,
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("/the/root/one.js".to_string(), 1, 28),
+            ("/the/root/one.js".to_string(), 1, 32),
+            ("/the/root/two.js".to_string(), 2, 1),
+            ("/the/root/two.js".to_string(), 2, 5),
+        ]
+    );
+
+    let mappings = map.mappings_in_original_range(
+        "/the/root/two.js",
+        Position { line: 1, column: 0 },
+        Position { line: 1, column: 6 },
+    );
+    let found: Vec<_> = mappings
+        .iter()
+        .map(|it| (it.generated.line, it.generated.column))
+        .collect();
+    assert_eq!(found, vec![(2, 1), (2, 5)]);
+}
+
+#[test]
+fn test_shift_and_slice() {
+    use crate::source_map::Position;
+
+    let mut map = create_consumer(testMap, None).unwrap();
+    let map = map.as_basic_consumer_mut();
+    let original_at = |consumer: &mut BasicConsumer, line, column| {
+        let mapping = consumer.original_position_for(Position { line, column }, None)?;
+        let original = mapping.original?;
+        Some((mapping.source?, original.line, original.column))
+    };
+
+    // "/* banner */\n(function(){" in front of the code.
+    let mut shifted =
+        BasicConsumer::from_source_map(&mut map.prepend("/* banner */\n(function(){"), None);
+    assert_eq!(
+        original_at(&mut shifted, 2, 13),
+        Some(("/the/root/one.js".to_string(), 1, 1))
+    );
+    assert_eq!(
+        original_at(&mut shifted, 3, 1),
+        Some(("/the/root/two.js".to_string(), 1, 1))
+    );
+    assert_eq!(original_at(&mut shifted, 1, 1), None);
+
+    let mut sliced = BasicConsumer::from_source_map(&mut map.slice(2, 3), None);
+    assert_eq!(sliced.get_sources(), vec!["/the/root/two.js".to_string()]);
+    assert_eq!(
+        original_at(&mut sliced, 1, 1),
+        Some(("/the/root/two.js".to_string(), 1, 1))
+    );
+    assert_eq!(original_at(&mut sliced, 2, 1), None);
+    let sliced = BasicConsumer::from_source_map(&mut map.slice(3, 1), None);
+    assert!(sliced.get_sources().is_empty());
+
+    // Negative shifts drop the mappings that would land before 1:0.
+    let mut generator = map.shift(-1, 0);
+    assert!(generator.as_json().mappings.unwrap().starts_with("CAAC,"));
+    let mut shifted = BasicConsumer::from_source_map(&mut generator, None);
+    assert_eq!(
+        original_at(&mut shifted, 1, 1),
+        Some(("/the/root/two.js".to_string(), 1, 1))
+    );
+    assert_eq!(original_at(&mut shifted, 2, 1), None);
+
+    let mut generator = map.shift(0, -5);
+    assert!(generator.as_json().mappings.unwrap().starts_with("AAAK,"));
+    let mut shifted = BasicConsumer::from_source_map(&mut generator, None);
+    assert_eq!(
+        original_at(&mut shifted, 1, 0),
+        Some(("/the/root/one.js".to_string(), 1, 5))
+    );
+    assert_eq!(
+        original_at(&mut shifted, 1, 4),
+        Some(("/the/root/one.js".to_string(), 1, 11))
+    );
+
+    let mut generator = map.shift(i32::MIN, i32::MIN);
+    assert_eq!(generator.as_json().mappings.as_deref(), Some(""));
+}
+
+#[test]
+fn test_is_ignored() {
+    let mut map = create_consumer(
+        r#"{"version":3,"sources":["a.js","node_modules/b.js"],"names":[],"mappings":"","ignoreList":[1]}"#,
+        None,
+    )
+    .unwrap();
+    assert!(!map.is_ignored("a.js"));
+    assert!(map.is_ignored("node_modules/b.js"));
+    assert!(!map.is_ignored("missing.js"));
+
+    let mut map = create_consumer(
+        r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"","x_google_ignoreList":[0]}"#,
+        None,
+    )
+    .unwrap();
+    assert!(map.is_ignored("a.js"));
+
+    let mut map = create_consumer(
+        r#"{
+          "version": 3,
+          "sections": [
+            { "offset": { "line": 0, "column": 0 }, "map": { "version": 3, "sources": ["a.js"], "names": [], "mappings": "AAAA" } },
+            { "offset": { "line": 1, "column": 0 }, "map": { "version": 3, "sources": ["vendor.js"], "names": [], "mappings": "AAAA", "ignoreList": [0] } }
+          ]
+        }"#,
+        None,
+    )
+    .unwrap();
+    assert!(!map.is_ignored("a.js"));
+    assert!(map.is_ignored("vendor.js"));
+}
+
+#[test]
+fn test_debug_id() {
+    let map = create_consumer(
+        r#"{"version":3,"sources":[],"names":[],"mappings":"","debugId":"85314830-023f-4cf1-a267-535f4e37bb17"}"#,
+        None,
+    )
+    .unwrap();
+    assert_eq!(map.debug_id(), Some("85314830-023f-4cf1-a267-535f4e37bb17"));
+
+    // Older tools write `debug_id`.
+    let map = create_consumer(
+        r#"{"version":3,"sources":[],"names":[],"mappings":"","debug_id":"85314830-023f-4cf1-a267-535f4e37bb17"}"#,
+        None,
+    )
+    .unwrap();
+    assert_eq!(map.debug_id(), Some("85314830-023f-4cf1-a267-535f4e37bb17"));
+
+    let map = create_consumer(testMap, None).unwrap();
+    assert_eq!(map.debug_id(), None);
+}
+
+#[test]
+fn test_scopes() {
+    use crate::generator::SourceMapGenerator;
+    use crate::mapping::Mapping;
+    use crate::scopes::{GeneratedRange, OriginalScope, ScopeReference};
+    use crate::source_map::Position;
+
+    // `function add(a, b) { return a + b; }` minified to `function n(r,t){return r+t}`.
+    let mut generator = SourceMapGenerator::new(Some("min.js".to_string()), None, false);
+    generator.add_mapping(Mapping {
+        generated: Position {
+            line: 1,
+            column: 16,
+        },
+        original: Some(Position {
+            line: 1,
+            column: 21,
+        }),
+        source: Some("add.js".to_string()),
+        name: None,
+        last_generated_column: None,
+    });
+    generator.set_original_scopes(
+        "add.js",
+        vec![OriginalScope {
+            start: Position { line: 1, column: 0 },
+            end: Position {
+                line: 1,
+                column: 37,
+            },
+            kind: Some("function".to_string()),
+            name: Some("add".to_string()),
+            is_stack_frame: true,
+            variables: vec!["a".to_string(), "b".to_string()],
+            children: vec![],
+        }],
+    );
+    generator.add_generated_range(GeneratedRange {
+        start: Position { line: 1, column: 0 },
+        end: Position {
+            line: 1,
+            column: 27,
+        },
+        definition: Some(ScopeReference {
+            source: "add.js".to_string(),
+            index: 0,
+        }),
+        is_stack_frame: true,
+        bindings: vec![Some("r".to_string()), Some("t".to_string())],
+        ..Default::default()
+    });
+
+    let mut map = BasicConsumer::from_source_map(&mut generator, None);
+    assert_eq!(
+        map.source_map.original_scopes,
+        Some(vec!["AAOACCC,AqC".to_string()])
+    );
+    assert_eq!(
+        map.source_map.generated_ranges.as_deref(),
+        Some("AKAAIK,2B")
+    );
+
+    let generated = Position {
+        line: 1,
+        column: 16,
+    };
+    assert_eq!(
+        map.original_function_name_at(generated.clone()),
+        Some("add")
+    );
+    let scope = map.original_scope_at(generated).unwrap();
+    assert_eq!(scope.variables, vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(map.scopes().generated[0].bindings[1].as_deref(), Some("t"));
+    assert_eq!(
+        map.original_scope_at(Position {
+            line: 1,
+            column: 27
+        }),
+        None
+    );
+
+    let mut map = BasicConsumer::new(testMap, None);
+    assert!(map.scopes().is_empty());
+}
+
+#[test]
+fn test_range_mappings() {
+    use crate::source_map::Position;
+
+    // `  foo(bar)` copied verbatim from line 3, column 4 of a.js, where the
+    // range mapping ends at an unmapped segment.
+    let mut map = BasicConsumer::new(
+        r#"{"version":3,"sources":["a.js"],"names":["foo"],"mappings":"EAEIA,Q","rangeMappings":"A"}"#,
+        None,
+    );
+    let lookup = |map: &mut BasicConsumer, column| {
+        let mapping = map.original_position_for(Position { line: 1, column }, None)?;
+        let original = mapping.original?;
+        Some((original.line, original.column, mapping.name))
+    };
+
+    assert!(map.is_range_mapping(&Position { line: 1, column: 2 }));
+    assert!(!map.is_range_mapping(&Position {
+        line: 1,
+        column: 10
+    }));
+    assert_eq!(lookup(&mut map, 2), Some((3, 4, Some("foo".to_string()))));
+    assert_eq!(lookup(&mut map, 5), Some((3, 7, None)));
+    assert_eq!(lookup(&mut map, 9), Some((3, 11, None)));
+    assert_eq!(lookup(&mut map, 10), None);
+
+    // Searching forward lands on the start of the range mapping.
+    let mapping = map
+        .original_position_for(
+            Position { line: 1, column: 0 },
+            Some(source_map_mappings::Bias::LeastUpperBound),
+        )
+        .unwrap();
+    assert_eq!((mapping.generated.line, mapping.generated.column), (1, 2));
+    let original = mapping.original.unwrap();
+    assert_eq!((original.line, original.column), (3, 4));
+    assert_eq!(mapping.name.as_deref(), Some("foo"));
+
+    assert_eq!(
+        map.shift(1, 0).as_json().range_mappings.as_deref(),
+        Some(";A")
+    );
+
+    // Without `rangeMappings` every column maps to the start of the segment,
+    // name included.
+    let mut map = BasicConsumer::new(
+        r#"{"version":3,"sources":["a.js"],"names":["foo"],"mappings":"EAEIA,Q"}"#,
+        None,
+    );
+    assert!(!map.is_range_mapping(&Position { line: 1, column: 2 }));
+    assert_eq!(lookup(&mut map, 5), Some((3, 4, Some("foo".to_string()))));
+}
+
+#[test]
+fn test_function_name_for() {
+    use crate::source_map::Position;
+
+    let raw = r#"{
+      "version": 3,
+      "sources": ["a.js", "b.js"],
+      "names": [],
+      "mappings": "AAAA,aACE",
+      "x_facebook_sources": [[{"names": ["<global>", "foo"], "mappings": "AAA;ACC"}], null],
+      "x_facebook_offsets": [0, null],
+      "x_metro_module_paths": ["a.js"]
+    }"#;
+    let mut map = create_consumer(raw, None).unwrap();
+    let source_map = &map.as_basic_consumer().source_map;
+    assert_eq!(source_map.x_facebook_offsets, Some(vec![Some(0), None]));
+    assert_eq!(
+        source_map.x_metro_module_paths,
+        Some(vec!["a.js".to_string()])
+    );
+
+    assert_eq!(
+        map.function_name_for("a.js", Position { line: 3, column: 0 }),
+        Some("foo".to_string())
+    );
+    assert_eq!(
+        map.function_name_for("b.js", Position { line: 1, column: 0 }),
+        None
+    );
+    assert_eq!(
+        map.original_function_name_for(Position { line: 1, column: 5 }),
+        Some("<global>".to_string())
+    );
+    assert_eq!(
+        map.original_function_name_for(Position {
+            line: 1,
+            column: 15
+        }),
+        Some("foo".to_string())
+    );
+}
+
+#[test]
+fn test_original_position_for_wasm_offset() {
+    use crate::source_map::Position;
+
+    // Offsets 0x10 and 0x2a of a module map to lib.rs 3:4 and 8:0.
+    let mut map = BasicConsumer::new(
+        r#"{"version":3,"sources":["lib.rs"],"names":[],"mappings":"gBAEI,0BAKJ"}"#,
+        None,
+    );
+    let original = |map: &mut BasicConsumer, offset| {
+        let mapping = map.original_position_for_wasm_offset(offset)?;
+        Some((mapping.source?, mapping.original?))
+    };
+    assert_eq!(original(&mut map, 0x0f), None);
+    assert_eq!(
+        original(&mut map, 0x10),
+        Some(("lib.rs".to_string(), Position { line: 3, column: 4 }))
+    );
+    assert_eq!(
+        original(&mut map, 0x30),
+        Some(("lib.rs".to_string(), Position { line: 8, column: 0 }))
+    );
+    // Past what a column can hold.
+    assert_eq!(original(&mut map, u32::MAX), None);
+}
+
+#[test]
+fn test_lenient_parsing() {
+    use crate::lenient::Warning;
+    use crate::source_map::Position;
+
+    let raw = r#")]}'
+{"version":"3","sections":[{"offset":{"line":0,"column":0},"map":{"version":"3","sources":[null,"b.scss"],"names":[null],"mappings":"AAAA,C$A,ECAAA"}}]}"#;
+    assert!(create_consumer(raw, None).is_err());
+    assert!(create_consumer_with_options(raw, None, &ParseOptions::default()).is_err());
+
+    let options = ParseOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let (mut map, warnings) = create_consumer_with_options(raw, None, &options).unwrap();
+    assert_eq!(
+        warnings,
+        vec![
+            Warning::XssiPrefix,
+            Warning::StringVersion("3".to_string()),
+            Warning::StringVersion("3".to_string()),
+            Warning::NullSource(0),
+            Warning::NullName(0),
+            Warning::InvalidSegment {
+                line: 1,
+                segment: 1
+            },
+        ]
+    );
+
+    let mapping = map
+        .original_position_for(Position { line: 1, column: 2 }, None)
+        .unwrap();
+    assert_eq!(mapping.source.as_deref(), Some("b.scss"));
+    assert_eq!(mapping.original, Some(Position { line: 1, column: 0 }));
+    assert_eq!(mapping.name, None);
+
+    // The first segment pointed at the null source.
+    let mapping = map.original_position_for(Position { line: 1, column: 0 }, None);
+    assert_eq!(mapping.and_then(|it| it.source), None);
+}
+
+#[test]
+fn test_limits() {
+    use crate::limits::{LimitError, Limits};
+
+    let limits = Limits {
+        max_json_size: 200,
+        max_lines: 10,
+        ..Default::default()
+    };
+    let options = ParseOptions {
+        lenient: false,
+        limits: Some(limits),
+    };
+    let parse = |raw: &str| create_consumer_with_options(raw, None, &options).map(|_| ());
+
+    // A map of nothing but semicolons is rejected before it is decoded.
+    let raw = format!(
+        r#"{{"version":3,"sources":[],"names":[],"mappings":"{}"}}"#,
+        ";".repeat(100)
+    );
+    assert!(matches!(
+        parse(&raw),
+        Err(ParseError::Limit(LimitError::Lines(10)))
+    ));
+    assert!(matches!(
+        parse(&raw.replace(&";".repeat(100), &";".repeat(200))),
+        Err(ParseError::Limit(LimitError::JsonSize(200)))
+    ));
+    assert!(matches!(
+        parse(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA;;;////////A"}"#),
+        Err(ParseError::Limit(LimitError::VlqDigits {
+            line: 4,
+            limit: 7
+        }))
+    ));
+    assert!(matches!(parse("{"), Err(ParseError::Json(_))));
+    assert!(parse(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA;;;"}"#).is_ok());
+
+    // Seven digits, but the column is over u32::MAX.
+    let raw = r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA,+/////PAAA"}"#;
+    let options = ParseOptions {
+        lenient: false,
+        limits: Some(Limits::default()),
+    };
+    assert!(matches!(
+        create_consumer_with_options(raw, None, &options),
+        Err(ParseError::Mappings(
+            source_map_mappings::Error::UnexpectedlyBigNumber
+        ))
+    ));
+    let raw = r#"{"version":3,"sections":[{"offset":{"line":0,"column":0},"map":{"version":3,"sources":["a.js"],"names":[],"mappings":"ADAA"}}]}"#;
+    assert!(matches!(
+        create_consumer_with_options(raw, None, &options),
+        Err(ParseError::Mappings(
+            source_map_mappings::Error::UnexpectedNegativeNumber
+        ))
+    ));
+}
+
+#[test]
+fn test_null_sources_content() {
+    let mut map = BasicConsumer::new(
+        r#"{"version":3,"sources":["a.js","b.js"],"sourcesContent":[null,"b"],"names":[],"mappings":"AAAA,CCAA"}"#,
+        None,
+    );
+    assert!(!map.has_contents_of_all_sources());
+    assert_eq!(map.source_content_for("a.js", Some(false)), None);
+    assert_eq!(
+        map.source_content_for("b.js", Some(false)).as_deref(),
+        Some("b")
+    );
+}
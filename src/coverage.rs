@@ -0,0 +1,613 @@
+//!
+//! Remap V8 precise coverage collected against generated code onto the
+//! original sources, and write it out as Istanbul JSON or LCOV.
+//!
+//! ref: https://chromedevtools.github.io/devtools-protocol/tot/Profiler/#type-ScriptCoverage
+//!
+use crate::consumer::Consumer;
+use crate::line_index::{ColumnUnit, LineIndex};
+use crate::mapping::Mapping;
+use crate::source_map::Position;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Coverage of a single script, as returned by `Profiler.takePreciseCoverage`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptCoverage {
+    #[serde(default)]
+    pub script_id: String,
+    pub url: String,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCoverage {
+    pub function_name: String,
+    pub ranges: Vec<CoverageRange>,
+    #[serde(default)]
+    pub is_block_coverage: bool,
+}
+
+/// A range of the script source. V8 counts offsets in UTF-16 code units.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageRange {
+    pub start_offset: usize,
+    pub end_offset: usize,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FunctionEntry {
+    pub name: String,
+    pub decl: Range,
+    pub loc: Range,
+    pub line: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BranchEntry {
+    pub loc: Range,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub locations: Vec<Range>,
+    pub line: i32,
+}
+
+/// Istanbul coverage of one original source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileCoverage {
+    pub path: String,
+    #[serde(rename = "statementMap")]
+    pub statement_map: BTreeMap<usize, Range>,
+    #[serde(rename = "fnMap")]
+    pub fn_map: BTreeMap<usize, FunctionEntry>,
+    #[serde(rename = "branchMap")]
+    pub branch_map: BTreeMap<usize, BranchEntry>,
+    pub s: BTreeMap<usize, u64>,
+    pub f: BTreeMap<usize, u64>,
+    pub b: BTreeMap<usize, Vec<u64>>,
+}
+
+type Point = (i32, i32);
+
+#[derive(Default)]
+struct SourceCoverage {
+    statements: Vec<(Point, Point, u64)>,
+    functions: Vec<(String, Point, Point, u64)>,
+    branches: Vec<(Point, Point, u64)>,
+}
+
+/// Coverage of original sources, accumulated over one or more scripts.
+#[derive(Default)]
+pub struct CoverageMap {
+    sources: BTreeMap<String, SourceCoverage>,
+}
+
+impl CoverageMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    ///
+    /// Remap the coverage of one script. `generated_code` is the script source
+    /// V8 reported offsets against, and `consumer` its source map.
+    ///
+    /// Nested V8 ranges are flattened first, so the innermost range decides the
+    /// count of every generated character. Each flattened range is then split
+    /// over the mappings it overlaps and attributed to their original sources.
+    ///
+    pub fn add_script(
+        &mut self,
+        script: &ScriptCoverage,
+        generated_code: &str,
+        consumer: &mut Consumer,
+    ) {
+        let generated_text = LineIndex::new(generated_code);
+        // V8 reports offsets in UTF-16 code units.
+        let to_position = |offset: usize| {
+            generated_text.offset_to_position(generated_text.convert_offset(
+                offset,
+                ColumnUnit::Utf16,
+                ColumnUnit::Utf8,
+            )?)
+        };
+
+        let mut ranges: Vec<&CoverageRange> = vec![];
+        for (index, function) in script.functions.iter().enumerate() {
+            ranges.extend(function.ranges.iter());
+
+            let outer = match function.ranges.first() {
+                Some(outer) => outer,
+                None => continue,
+            };
+            // V8 reports the script itself as a nameless function at offset 0.
+            let is_script =
+                index == 0 && function.function_name.is_empty() && outer.start_offset == 0;
+
+            if !is_script {
+                let (start, end) = match (
+                    to_position(outer.start_offset),
+                    to_position(outer.end_offset),
+                ) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => continue,
+                };
+                let mappings = consumer.mappings_in_generated_range(start, end);
+                if let Some((source, original_start, original_end)) =
+                    envelope(&mappings, &generated_text)
+                {
+                    let name = mappings
+                        .iter()
+                        .find_map(|it| it.name.clone())
+                        .filter(|_| function.function_name.is_empty())
+                        .unwrap_or_else(|| function.function_name.clone());
+                    self.source(&source).functions.push((
+                        name,
+                        original_start,
+                        original_end,
+                        outer.count,
+                    ));
+                }
+            }
+
+            if function.is_block_coverage {
+                for block in function.ranges.iter().skip(1) {
+                    let (start, end) = match (
+                        to_position(block.start_offset),
+                        to_position(block.end_offset),
+                    ) {
+                        (Some(start), Some(end)) => (start, end),
+                        _ => continue,
+                    };
+                    let mappings = consumer.mappings_in_generated_range(start, end);
+                    if let Some((source, original_start, original_end)) =
+                        envelope(&mappings, &generated_text)
+                    {
+                        self.source(&source).branches.push((
+                            original_start,
+                            original_end,
+                            block.count,
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (start_offset, end_offset, count) in flatten(ranges) {
+            let (start, end) = match (to_position(start_offset), to_position(end_offset)) {
+                (Some(start), Some(end)) => (start, end),
+                _ => continue,
+            };
+            for mapping in consumer.mappings_in_generated_range(start.clone(), end.clone()) {
+                if let Some((source, original_start, original_end)) =
+                    original_span(&mapping, &end, &generated_text)
+                {
+                    self.source(&source)
+                        .statements
+                        .push((original_start, original_end, count));
+                }
+            }
+        }
+    }
+
+    fn source(&mut self, source: &str) -> &mut SourceCoverage {
+        self.sources.entry(source.to_string()).or_default()
+    }
+
+    /// Istanbul coverage for every original source, keyed by source path.
+    pub fn files(&self) -> BTreeMap<String, FileCoverage> {
+        self.sources
+            .iter()
+            .map(|(path, coverage)| (path.clone(), file_coverage(path, coverage)))
+            .collect()
+    }
+
+    /// Istanbul `coverage-final.json` content.
+    pub fn to_istanbul_json(&self) -> String {
+        serde_json::to_string(&self.files()).unwrap()
+    }
+
+    /// LCOV tracefile content.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (path, file) in self.files() {
+            lcov.push_str("TN:\n");
+            lcov.push_str(&format!("SF:{}\n", path));
+
+            for function in file.fn_map.values() {
+                lcov.push_str(&format!("FN:{},{}\n", function.line, function.name));
+            }
+            let mut functions_hit = 0;
+            for (id, function) in file.fn_map.iter() {
+                let count = file.f[id];
+                if count > 0 {
+                    functions_hit += 1;
+                }
+                lcov.push_str(&format!("FNDA:{},{}\n", count, function.name));
+            }
+            lcov.push_str(&format!("FNF:{}\n", file.fn_map.len()));
+            lcov.push_str(&format!("FNH:{}\n", functions_hit));
+
+            let mut branches_hit = 0;
+            for (id, branch) in file.branch_map.iter() {
+                for (index, count) in file.b[id].iter().enumerate() {
+                    if *count > 0 {
+                        branches_hit += 1;
+                    }
+                    lcov.push_str(&format!(
+                        "BRDA:{},{},{},{}\n",
+                        branch.line, id, index, count
+                    ));
+                }
+            }
+            lcov.push_str(&format!(
+                "BRF:{}\n",
+                file.b.values().map(|it| it.len()).sum::<usize>()
+            ));
+            lcov.push_str(&format!("BRH:{}\n", branches_hit));
+
+            // Like Istanbul, a line is as covered as the best statement that
+            // starts on it.
+            let mut lines: BTreeMap<i32, u64> = BTreeMap::new();
+            for (id, statement) in file.statement_map.iter() {
+                let count = file.s[id];
+                let line = lines.entry(statement.start.line).or_insert(count);
+                *line = (*line).max(count);
+            }
+            for (line, count) in lines.iter() {
+                lcov.push_str(&format!("DA:{},{}\n", line, count));
+            }
+            lcov.push_str(&format!("LF:{}\n", lines.len()));
+            lcov.push_str(&format!(
+                "LH:{}\n",
+                lines.values().filter(|it| **it > 0).count()
+            ));
+            lcov.push_str("end_of_record\n");
+        }
+        lcov
+    }
+}
+
+fn utf16_len(text: &str) -> i32 {
+    text.encode_utf16().count() as i32
+}
+
+/// The original span covered by `mapping`, up to `end` in generated code. The
+/// original span is assumed to be as long as the generated one.
+fn original_span(
+    mapping: &Mapping,
+    end: &Position,
+    generated_text: &LineIndex,
+) -> Option<(String, Point, Point)> {
+    let source = mapping.source.clone()?;
+    let original = mapping.original.as_ref()?;
+
+    let line_end = generated_text
+        .line(mapping.generated.line)
+        .map(utf16_len)
+        .unwrap_or(mapping.generated.column);
+    let mut generated_end = mapping.last_generated_column.unwrap_or(line_end);
+    if mapping.generated.line == end.line {
+        generated_end = generated_end.min(end.column);
+    }
+    let length = (generated_end - mapping.generated.column).max(1);
+
+    Some((
+        source,
+        (original.line, original.column),
+        (original.line, original.column + length),
+    ))
+}
+
+/// The original span from the first to the last mapping of the source the
+/// first mapping belongs to.
+fn envelope(mappings: &[Mapping], generated_text: &LineIndex) -> Option<(String, Point, Point)> {
+    let mut result: Option<(String, Point, Point)> = None;
+    for mapping in mappings {
+        let end = Position {
+            line: i32::MAX,
+            column: 0,
+        };
+        if let Some((source, start, end)) = original_span(mapping, &end, generated_text) {
+            match result.as_mut() {
+                None => result = Some((source, start, end)),
+                Some(it) if it.0 == source => {
+                    it.1 = it.1.min(start);
+                    it.2 = it.2.max(end);
+                }
+                _ => {}
+            }
+        }
+    }
+    result
+}
+
+/// Turn properly nested V8 ranges into non-overlapping ones, letting inner
+/// ranges override the count of the ranges around them.
+fn flatten(mut ranges: Vec<&CoverageRange>) -> Vec<(usize, usize, u64)> {
+    ranges.sort_by(|a, b| {
+        a.start_offset
+            .cmp(&b.start_offset)
+            .then(b.end_offset.cmp(&a.end_offset))
+    });
+
+    let mut flat = vec![];
+    let mut emit = |start: usize, end: usize, count: u64| {
+        if start < end {
+            flat.push((start, end, count));
+        }
+    };
+
+    let mut stack: Vec<(usize, u64)> = vec![];
+    let mut cursor = 0;
+    for range in ranges {
+        while let Some(&(end, count)) = stack.last() {
+            if end > range.start_offset {
+                break;
+            }
+            emit(cursor, end, count);
+            cursor = cursor.max(end);
+            stack.pop();
+        }
+        if let Some(&(_, count)) = stack.last() {
+            emit(cursor, range.start_offset, count);
+        }
+        cursor = range.start_offset;
+        stack.push((range.end_offset, range.count));
+    }
+    while let Some((end, count)) = stack.pop() {
+        emit(cursor, end, count);
+        cursor = cursor.max(end);
+    }
+
+    flat
+}
+
+/// Merge overlapping ranges of one source. Where ranges overlap, the highest
+/// count wins; touching ranges with the same count are joined.
+fn merge(ranges: &[(Point, Point, u64)]) -> Vec<(Point, Point, u64)> {
+    let mut events: Vec<(Point, bool, u64)> = vec![];
+    for (start, end, count) in ranges {
+        if start < end {
+            events.push((*start, true, *count));
+            events.push((*end, false, *count));
+        }
+    }
+    events.sort();
+
+    let mut active: BTreeMap<u64, usize> = BTreeMap::new();
+    let mut merged: Vec<(Point, Point, u64)> = vec![];
+    let mut previous: Option<Point> = None;
+    for (point, is_start, count) in events {
+        if let (Some(previous), Some((&best, _))) = (previous, active.iter().next_back()) {
+            if previous < point {
+                match merged.last_mut() {
+                    Some(last) if last.1 == previous && last.2 == best => last.1 = point,
+                    _ => merged.push((previous, point, best)),
+                }
+            }
+        }
+
+        if is_start {
+            *active.entry(count).or_default() += 1;
+        } else if let Some(n) = active.get_mut(&count) {
+            *n -= 1;
+            if *n == 0 {
+                active.remove(&count);
+            }
+        }
+        previous = Some(point);
+    }
+
+    merged
+}
+
+fn to_range(start: Point, end: Point) -> Range {
+    Range {
+        start: Position {
+            line: start.0,
+            column: start.1,
+        },
+        end: Position {
+            line: end.0,
+            column: end.1,
+        },
+    }
+}
+
+fn file_coverage(path: &str, coverage: &SourceCoverage) -> FileCoverage {
+    let mut file = FileCoverage {
+        path: path.to_string(),
+        statement_map: Default::default(),
+        fn_map: Default::default(),
+        branch_map: Default::default(),
+        s: Default::default(),
+        f: Default::default(),
+        b: Default::default(),
+    };
+
+    for (id, (start, end, count)) in merge(&coverage.statements).into_iter().enumerate() {
+        file.statement_map.insert(id, to_range(start, end));
+        file.s.insert(id, count);
+    }
+
+    let mut functions = coverage.functions.clone();
+    functions.sort_by_key(|it| (it.1, it.2, it.0.clone()));
+    functions.dedup_by(|a, b| {
+        if a.0 == b.0 && a.1 == b.1 && a.2 == b.2 {
+            b.3 += a.3;
+            true
+        } else {
+            false
+        }
+    });
+    for (id, (name, start, end, count)) in functions.into_iter().enumerate() {
+        let name = if name.is_empty() {
+            format!("(anonymous_{})", id)
+        } else {
+            name
+        };
+        file.fn_map.insert(
+            id,
+            FunctionEntry {
+                name,
+                decl: to_range(start, end),
+                loc: to_range(start, end),
+                line: start.0,
+            },
+        );
+        file.f.insert(id, count);
+    }
+
+    let mut branches = coverage.branches.clone();
+    branches.sort();
+    for (id, (start, end, count)) in branches.into_iter().enumerate() {
+        file.branch_map.insert(
+            id,
+            BranchEntry {
+                loc: to_range(start, end),
+                kind: "branch".to_string(),
+                locations: vec![to_range(start, end)],
+                line: start.0,
+            },
+        );
+        file.b.insert(id, vec![count]);
+    }
+
+    file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::create_consumer;
+    use crate::generator::SourceMapGenerator;
+
+    fn range(start_offset: usize, end_offset: usize, count: u64) -> CoverageRange {
+        CoverageRange {
+            start_offset,
+            end_offset,
+            count,
+        }
+    }
+
+    #[test]
+    fn flattens_nested_ranges() {
+        let ranges = [
+            range(0, 100, 1),
+            range(10, 20, 0),
+            range(30, 60, 5),
+            range(40, 50, 0),
+        ];
+        assert_eq!(
+            flatten(ranges.iter().collect()),
+            vec![
+                (0, 10, 1),
+                (10, 20, 0),
+                (20, 30, 1),
+                (30, 40, 5),
+                (40, 50, 0),
+                (50, 60, 5),
+                (60, 100, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_overlaps() {
+        let ranges = [
+            ((1, 0), (1, 10), 1),
+            ((1, 5), (1, 15), 3),
+            ((1, 15), (1, 20), 3),
+            ((2, 0), (2, 4), 0),
+        ];
+        assert_eq!(
+            merge(&ranges),
+            vec![
+                ((1, 0), (1, 5), 1),
+                ((1, 5), (1, 20), 3),
+                ((2, 0), (2, 4), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn remaps_script() {
+        // Original a.js:
+        //   function used() { return 1; }
+        //   function unused() { return 2; }
+        // Generated: both functions on one line.
+        let generated = "function used(){return 1}function unused(){return 2}";
+        let mut generator = SourceMapGenerator::new(None, None, false);
+        for (generated_column, original_line, original_column) in
+            [(0, 1, 0), (16, 1, 18), (25, 2, 0), (43, 2, 20)]
+        {
+            generator.add_mapping(Mapping {
+                generated: Position {
+                    line: 1,
+                    column: generated_column,
+                },
+                original: Some(Position {
+                    line: original_line,
+                    column: original_column,
+                }),
+                source: Some("a.js".to_string()),
+                name: None,
+                last_generated_column: None,
+            });
+        }
+        let mut consumer = create_consumer(&generator.as_string(), None).unwrap();
+
+        let script: ScriptCoverage = serde_json::from_str(
+            r#"{
+              "scriptId": "1",
+              "url": "file:///out.js",
+              "functions": [
+                { "functionName": "", "isBlockCoverage": true, "ranges": [{ "startOffset": 0, "endOffset": 52, "count": 1 }] },
+                { "functionName": "used", "isBlockCoverage": true, "ranges": [{ "startOffset": 0, "endOffset": 25, "count": 4 }] },
+                { "functionName": "unused", "isBlockCoverage": true, "ranges": [{ "startOffset": 25, "endOffset": 52, "count": 0 }] }
+              ]
+            }"#,
+        )
+        .unwrap();
+
+        let mut coverage = CoverageMap::new();
+        coverage.add_script(&script, generated, &mut consumer);
+        let files = coverage.files();
+        let file = &files["a.js"];
+
+        let names: Vec<_> = file.fn_map.values().map(|it| it.name.as_str()).collect();
+        assert_eq!(names, vec!["used", "unused"]);
+        assert_eq!(file.f.values().copied().collect::<Vec<_>>(), vec![4, 0]);
+        assert_eq!(file.fn_map[&1].line, 2);
+
+        let statements: Vec<_> = file
+            .statement_map
+            .iter()
+            .map(|(id, it)| (it.start.line, it.start.column, file.s[id]))
+            .collect();
+        assert_eq!(
+            statements,
+            vec![(1, 0, 4), (1, 18, 4), (2, 0, 0), (2, 20, 0)]
+        );
+
+        let lcov = coverage.to_lcov();
+        assert!(lcov.starts_with(
+            "TN:\nSF:a.js\nFN:1,used\nFN:2,unused\nFNDA:4,used\nFNDA:0,unused\nFNF:2\nFNH:1\n"
+        ));
+        assert!(lcov.contains("DA:1,4\nDA:2,0\nLF:2\nLH:1\nend_of_record\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&coverage.to_istanbul_json()).unwrap();
+        assert_eq!(json["a.js"]["fnMap"]["0"]["name"], "used");
+        assert_eq!(json["a.js"]["s"]["2"], 0);
+    }
+}
@@ -0,0 +1,361 @@
+//!
+//! Remap Chrome `.cpuprofile` files recorded against generated code onto the
+//! original sources.
+//!
+//! ref: https://chromedevtools.github.io/devtools-protocol/tot/Profiler/#type-Profile
+//!
+use crate::consumer::Consumer;
+use crate::source_map::Position;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuProfile {
+    pub nodes: Vec<ProfileNode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<Vec<u32>>,
+    /// Everything else (`startTime`, `endTime`, `timeDeltas`, ...) is kept as is.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileNode {
+    pub id: u32,
+    pub call_frame: CallFrame,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hit_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_ticks: Option<Vec<PositionTick>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A stack frame. Unlike the rest of this crate, line and column numbers are
+/// 0-based here, as in the DevTools protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    pub function_name: String,
+    pub script_id: String,
+    pub url: String,
+    pub line_number: i32,
+    pub column_number: i32,
+}
+
+/// Ticks collected on a 1-based line of the node's script.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionTick {
+    pub line: i32,
+    pub ticks: u64,
+}
+
+impl CpuProfile {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Rewrite `frame` to its original location. Returns `None` when the frame's
+/// script has no map or the location is unmapped.
+fn remap_frame(frame: &CallFrame, consumer: &mut Consumer) -> Option<CallFrame> {
+    if frame.line_number < 0 || frame.column_number < 0 {
+        return None;
+    }
+    let mapping = consumer.original_position_for(
+        Position {
+            line: frame.line_number + 1,
+            column: frame.column_number,
+        },
+        None,
+    )?;
+    let original = mapping.original?;
+
+    Some(CallFrame {
+        function_name: mapping.name.unwrap_or_else(|| frame.function_name.clone()),
+        script_id: frame.script_id.clone(),
+        url: mapping.source?,
+        line_number: original.line - 1,
+        column_number: original.column,
+    })
+}
+
+///
+/// Map the generated lines of position ticks to lines of the source of
+/// `frame`, the node's remapped frame, dropping ticks on lines that belong to
+/// another source.
+///
+/// Ticks carry no column, so they can only be remapped per generated line. A
+/// generated line whose mappings all point at one original line gets its ticks
+/// there. When they point at several, as on the single line of a minified
+/// bundle, the ticks go to the line of `frame` itself, the start of the
+/// function they were collected in.
+///
+fn remap_ticks(
+    ticks: &[PositionTick],
+    frame: &CallFrame,
+    consumer: &mut Consumer,
+) -> Vec<PositionTick> {
+    ticks
+        .iter()
+        .filter_map(|tick| {
+            let lines: BTreeSet<i32> = consumer
+                .mappings_in_generated_range(
+                    Position {
+                        line: tick.line,
+                        column: 0,
+                    },
+                    Position {
+                        line: tick.line.checked_add(1)?,
+                        column: 0,
+                    },
+                )
+                .into_iter()
+                .filter(|it| it.source.as_deref() == Some(frame.url.as_str()))
+                .filter_map(|it| Some(it.original?.line))
+                .collect();
+            let line = match lines.len() {
+                0 => return None,
+                1 => *lines.iter().next()?,
+                _ => frame.line_number + 1,
+            };
+            Some(PositionTick {
+                line,
+                ticks: tick.ticks,
+            })
+        })
+        .collect()
+}
+
+fn merge_ticks(into: &mut Vec<PositionTick>, ticks: Vec<PositionTick>) {
+    let mut lines: BTreeMap<i32, u64> = into.iter().map(|it| (it.line, it.ticks)).collect();
+    for tick in ticks {
+        *lines.entry(tick.line).or_default() += tick.ticks;
+    }
+    *into = lines
+        .into_iter()
+        .map(|(line, ticks)| PositionTick { line, ticks })
+        .collect();
+}
+
+///
+/// Rewrite every call frame of `profile` to its original location and
+/// function name. `consumers` holds the source map of each script, keyed by the
+/// script `url` the profile reports. Frames of other scripts are left alone.
+///
+/// Sibling nodes that end up on the same original frame are merged, summing
+/// their hit counts and position ticks, and their children are merged in turn.
+/// `samples` are rewritten to point at the merged nodes.
+///
+pub fn remap_profile(
+    profile: &CpuProfile,
+    consumers: &mut HashMap<String, Consumer>,
+) -> CpuProfile {
+    let mut remapped: HashMap<u32, ProfileNode> = HashMap::new();
+    for node in &profile.nodes {
+        let mut node = node.clone();
+        if let Some(consumer) = consumers.get_mut(&node.call_frame.url) {
+            if let Some(frame) = remap_frame(&node.call_frame, consumer) {
+                node.position_ticks = node
+                    .position_ticks
+                    .map(|ticks| remap_ticks(&ticks, &frame, consumer));
+                node.call_frame = frame;
+            }
+        }
+        remapped.insert(node.id, node);
+    }
+
+    let is_child: HashSet<u32> = profile
+        .nodes
+        .iter()
+        .flat_map(|it| it.children.iter().flatten().copied())
+        .collect();
+    let roots: Vec<u32> = profile
+        .nodes
+        .iter()
+        .map(|it| it.id)
+        .filter(|it| !is_child.contains(it))
+        .collect();
+
+    let mut nodes = vec![];
+    let mut id_map: HashMap<u32, u32> = HashMap::new();
+    merge_siblings(&roots, &remapped, &mut nodes, &mut id_map);
+
+    CpuProfile {
+        nodes,
+        samples: profile.samples.as_ref().map(|samples| {
+            samples
+                .iter()
+                .map(|it| id_map.get(it).copied().unwrap_or(*it))
+                .collect()
+        }),
+        extra: profile.extra.clone(),
+    }
+}
+
+/// Merge the nodes in `ids` that share a call frame, push them to `nodes` in
+/// pre-order and return the ids of the merged nodes.
+fn merge_siblings(
+    ids: &[u32],
+    remapped: &HashMap<u32, ProfileNode>,
+    nodes: &mut Vec<ProfileNode>,
+    id_map: &mut HashMap<u32, u32>,
+) -> Vec<u32> {
+    let mut groups: Vec<(CallFrame, Vec<u32>)> = vec![];
+    for id in ids {
+        let node = match remapped.get(id) {
+            Some(node) => node,
+            None => continue,
+        };
+        match groups.iter_mut().find(|it| it.0 == node.call_frame) {
+            Some(group) => group.1.push(*id),
+            None => groups.push((node.call_frame.clone(), vec![*id])),
+        }
+    }
+
+    let mut merged_ids = vec![];
+    for (_, members) in groups {
+        let mut merged = remapped[&members[0]].clone();
+        let mut children = vec![];
+        for id in &members {
+            let node = &remapped[id];
+            id_map.insert(*id, merged.id);
+            children.extend(node.children.iter().flatten().copied());
+            if *id != merged.id {
+                if let Some(hit_count) = node.hit_count {
+                    *merged.hit_count.get_or_insert(0) += hit_count;
+                }
+                if let Some(ticks) = node.position_ticks.clone() {
+                    merge_ticks(merged.position_ticks.get_or_insert_with(Vec::new), ticks);
+                }
+            }
+        }
+
+        let index = nodes.len();
+        merged_ids.push(merged.id);
+        nodes.push(merged);
+        let children = merge_siblings(&children, remapped, nodes, id_map);
+        nodes[index].children = if children.is_empty() {
+            None
+        } else {
+            Some(children)
+        };
+    }
+
+    merged_ids
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::create_consumer;
+
+    #[test]
+    fn remaps_and_merges_frames() {
+        // Generated line 1: `function a(){b()}function b(){}` where both `a`
+        // (at column 0) and a second copy of it (column 30) map back to `foo`
+        // in src/foo.js, and `b` (column 17) maps to `bar`.
+        let map = r#"{
+          "version": 3,
+          "sources": ["src/foo.js"],
+          "names": ["foo", "bar"],
+          "mappings": "AAAAA,iBAEAC,aAFAD"
+        }"#;
+        let mut consumers = HashMap::new();
+        consumers.insert(
+            "http://localhost/out.js".to_string(),
+            create_consumer(map, None).unwrap(),
+        );
+
+        let profile = CpuProfile::from_json(
+            r#"{
+              "nodes": [
+                { "id": 1, "callFrame": { "functionName": "(root)", "scriptId": "0", "url": "", "lineNumber": -1, "columnNumber": -1 }, "hitCount": 0, "children": [2, 3] },
+                { "id": 2, "callFrame": { "functionName": "a", "scriptId": "7", "url": "http://localhost/out.js", "lineNumber": 0, "columnNumber": 0 }, "hitCount": 1, "children": [4] },
+                { "id": 3, "callFrame": { "functionName": "c", "scriptId": "7", "url": "http://localhost/out.js", "lineNumber": 0, "columnNumber": 30 }, "hitCount": 2, "children": [5] },
+                { "id": 4, "callFrame": { "functionName": "b", "scriptId": "7", "url": "http://localhost/out.js", "lineNumber": 0, "columnNumber": 17 }, "hitCount": 3 },
+                { "id": 5, "callFrame": { "functionName": "b", "scriptId": "7", "url": "http://localhost/out.js", "lineNumber": 0, "columnNumber": 17 }, "hitCount": 4 }
+              ],
+              "startTime": 0,
+              "endTime": 100,
+              "samples": [2, 3, 4, 5, 1],
+              "timeDeltas": [1, 1, 1, 1, 1]
+            }"#,
+        )
+        .unwrap();
+
+        let remapped = remap_profile(&profile, &mut consumers);
+
+        let frames: Vec<_> = remapped
+            .nodes
+            .iter()
+            .map(|it| {
+                (
+                    it.id,
+                    it.call_frame.function_name.as_str(),
+                    it.call_frame.url.as_str(),
+                    it.call_frame.line_number,
+                    it.hit_count,
+                    it.children.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                (1, "(root)", "", -1, Some(0), Some(vec![2])),
+                (2, "foo", "src/foo.js", 0, Some(3), Some(vec![4])),
+                (4, "bar", "src/foo.js", 2, Some(7), None),
+            ]
+        );
+        assert_eq!(remapped.samples, Some(vec![2, 2, 4, 4, 1]));
+        assert_eq!(remapped.extra["endTime"], 100);
+
+        // The output is still a valid profile.
+        let json = remapped.to_json();
+        assert!(CpuProfile::from_json(&json).is_ok());
+        assert!(json.contains("\"timeDeltas\":[1,1,1,1,1]"));
+    }
+
+    #[test]
+    fn position_ticks() {
+        // Generated line 1 maps to lines 1, 3 and 5 of src/foo.js, generated
+        // line 2 to line 7 only. The function starts at generated column 10,
+        // line 3.
+        let map = r#"{
+          "version": 3,
+          "sources": ["src/foo.js"],
+          "names": [],
+          "mappings": "AAAA,UAEA,UAEE;AAEF"
+        }"#;
+        let mut consumers = HashMap::new();
+        consumers.insert("out.js".to_string(), create_consumer(map, None).unwrap());
+
+        let profile = CpuProfile::from_json(
+            r#"{
+              "nodes": [
+                { "id": 1, "callFrame": { "functionName": "f", "scriptId": "7", "url": "out.js", "lineNumber": 0, "columnNumber": 10 }, "hitCount": 8, "positionTicks": [{ "line": 1, "ticks": 5 }, { "line": 2, "ticks": 3 }, { "line": 9, "ticks": 1 }] }
+              ]
+            }"#,
+        )
+        .unwrap();
+
+        let remapped = remap_profile(&profile, &mut consumers);
+        let ticks: Vec<_> = remapped.nodes[0]
+            .position_ticks
+            .iter()
+            .flatten()
+            .map(|it| (it.line, it.ticks))
+            .collect();
+        assert_eq!(ticks, vec![(3, 5), (7, 3)]);
+    }
+}
@@ -0,0 +1,405 @@
+//!
+//! Compare two source maps by what they map rather than by their VLQ text.
+//!
+use crate::consumer::Consumer;
+use crate::mapping::Mapping;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMapDiff {
+    /// Mappings at generated positions only the new map has.
+    pub added: Vec<Mapping>,
+    /// Mappings at generated positions only the old map has.
+    pub removed: Vec<Mapping>,
+    /// `(old, new)` pairs at the same generated position that point somewhere
+    /// else, after renames are taken into account.
+    pub changed: Vec<(Mapping, Mapping)>,
+    pub unchanged: usize,
+    /// `(old, new)` source names, detected from mappings that kept their
+    /// original position but switched to a source the old map doesn't have.
+    pub renamed_sources: Vec<(String, String)>,
+    pub added_sources: Vec<String>,
+    pub removed_sources: Vec<String>,
+    /// `(old, new)` names, detected like `renamed_sources`.
+    pub renamed_names: Vec<(String, String)>,
+    /// Sources present in both maps, under their new name, whose
+    /// `sourcesContent` differs.
+    pub changed_contents: Vec<String>,
+}
+
+type Target = (Option<String>, Option<(i32, i32)>, Option<String>);
+
+fn target(mapping: &Mapping) -> Target {
+    (
+        mapping.source.clone(),
+        mapping.original.as_ref().map(|it| (it.line, it.column)),
+        mapping.name.clone(),
+    )
+}
+
+fn by_generated_position(mappings: Vec<Mapping>) -> BTreeMap<(i32, i32), Mapping> {
+    let mut result = BTreeMap::new();
+    for mapping in mappings {
+        result
+            .entry((mapping.generated.line, mapping.generated.column))
+            .or_insert(mapping);
+    }
+    result
+}
+
+///
+/// Pick a rename for every old value that isn't in `new_values` from the
+/// `(old, new)` candidates, preferring the most frequent one. Each new value is
+/// used at most once.
+///
+fn detect_renames(
+    candidates: Vec<(String, String)>,
+    old_values: &BTreeSet<String>,
+    new_values: &BTreeSet<String>,
+) -> Vec<(String, String)> {
+    let mut counts: BTreeMap<(String, String), usize> = BTreeMap::new();
+    for (old, new) in candidates {
+        if !new_values.contains(&old) && !old_values.contains(&new) {
+            *counts.entry((old, new)).or_default() += 1;
+        }
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    let mut renames: Vec<(String, String)> = vec![];
+    for ((old, new), _) in counts {
+        if renames.iter().all(|(o, n)| *o != old && *n != new) {
+            renames.push((old, new));
+        }
+    }
+    renames.sort();
+    renames
+}
+
+fn rename(value: &Option<String>, renames: &[(String, String)]) -> Option<String> {
+    value.as_ref().map(|value| {
+        renames
+            .iter()
+            .find(|(old, _)| old == value)
+            .map(|(_, new)| new.clone())
+            .unwrap_or_else(|| value.clone())
+    })
+}
+
+///
+/// Diff the flattened mappings of `old` and `new`, pairing them up by
+/// generated position. Either side may be a basic or an indexed map.
+///
+pub fn diff(old: &mut Consumer, new: &mut Consumer) -> SourceMapDiff {
+    let old_mappings = by_generated_position(old.all_mappings());
+    let new_mappings = by_generated_position(new.all_mappings());
+    let pairs: Vec<(&Mapping, &Mapping)> = old_mappings
+        .iter()
+        .filter_map(|(key, it)| Some((it, new_mappings.get(key)?)))
+        .collect();
+
+    let old_sources: BTreeSet<String> = old.get_sources().into_iter().collect();
+    let new_sources: BTreeSet<String> = new.get_sources().into_iter().collect();
+    let renamed_sources = detect_renames(
+        pairs
+            .iter()
+            .filter(|(a, b)| a.original == b.original)
+            .filter_map(|(a, b)| Some((a.source.clone()?, b.source.clone()?)))
+            .collect(),
+        &old_sources,
+        &new_sources,
+    );
+
+    let names_of = |mappings: &BTreeMap<(i32, i32), Mapping>| -> BTreeSet<String> {
+        mappings.values().filter_map(|it| it.name.clone()).collect()
+    };
+    let renamed_names = detect_renames(
+        pairs
+            .iter()
+            .filter(|(a, b)| {
+                a.original == b.original && rename(&a.source, &renamed_sources) == b.source
+            })
+            .filter_map(|(a, b)| Some((a.name.clone()?, b.name.clone()?)))
+            .collect(),
+        &names_of(&old_mappings),
+        &names_of(&new_mappings),
+    );
+
+    let mut result = SourceMapDiff {
+        added_sources: new_sources
+            .iter()
+            .filter(|it| !old_sources.contains(*it))
+            .filter(|it| renamed_sources.iter().all(|(_, new)| new != *it))
+            .cloned()
+            .collect(),
+        removed_sources: old_sources
+            .iter()
+            .filter(|it| !new_sources.contains(*it))
+            .filter(|it| renamed_sources.iter().all(|(old, _)| old != *it))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+
+    for (key, old_mapping) in &old_mappings {
+        match new_mappings.get(key) {
+            Some(new_mapping) => {
+                let (source, original, name) = target(old_mapping);
+                let renamed = (
+                    rename(&source, &renamed_sources),
+                    original,
+                    rename(&name, &renamed_names),
+                );
+                if renamed == target(new_mapping) {
+                    result.unchanged += 1;
+                } else {
+                    result
+                        .changed
+                        .push((old_mapping.clone(), new_mapping.clone()));
+                }
+            }
+            None => result.removed.push(old_mapping.clone()),
+        }
+    }
+    result.added = new_mappings
+        .iter()
+        .filter(|(key, _)| !old_mappings.contains_key(key))
+        .map(|(_, it)| it.clone())
+        .collect();
+
+    let renames: HashMap<&String, &String> = renamed_sources.iter().map(|(o, n)| (o, n)).collect();
+    for old_source in &old_sources {
+        let new_source = renames.get(old_source).copied().unwrap_or(old_source);
+        if !new_sources.contains(new_source) {
+            continue;
+        }
+        if old.source_content_for(old_source, Some(false))
+            != new.source_content_for(new_source, Some(false))
+        {
+            result.changed_contents.push(new_source.clone());
+        }
+    }
+
+    result.renamed_sources = renamed_sources;
+    result.renamed_names = renamed_names;
+    result
+}
+
+fn describe(mapping: &Mapping) -> String {
+    format!(
+        "{}:{} -> {}",
+        mapping.generated.line,
+        mapping.generated.column,
+        describe_target(mapping)
+    )
+}
+
+fn describe_target(mapping: &Mapping) -> String {
+    match (&mapping.source, &mapping.original) {
+        (Some(source), Some(original)) => {
+            let mut text = format!("{}:{}:{}", source, original.line, original.column);
+            if let Some(name) = &mapping.name {
+                text.push_str(&format!(" ({})", name));
+            }
+            text
+        }
+        _ => "(unmapped)".to_string(),
+    }
+}
+
+impl SourceMapDiff {
+    /// Number of mappings in the old map.
+    pub fn old_count(&self) -> usize {
+        self.unchanged + self.changed.len() + self.removed.len()
+    }
+
+    /// Percentage of the old map's mappings that were removed or now point
+    /// somewhere else.
+    pub fn moved_percent(&self) -> f64 {
+        match self.old_count() {
+            0 => 0.0,
+            count => (self.changed.len() + self.removed.len()) as f64 * 100.0 / count as f64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.renamed_sources.is_empty()
+            && self.added_sources.is_empty()
+            && self.removed_sources.is_empty()
+            && self.renamed_names.is_empty()
+            && self.changed_contents.is_empty()
+    }
+
+    /// Render the diff as a plain text report ending in a one-line summary.
+    pub fn to_report(&self) -> String {
+        let mut report = String::new();
+        let mut section = |title: &str, lines: Vec<String>| {
+            if lines.is_empty() {
+                return;
+            }
+            report.push_str(title);
+            report.push_str(":\n");
+            for line in lines {
+                report.push_str("  ");
+                report.push_str(&line);
+                report.push('\n');
+            }
+        };
+        let renames = |renames: &[(String, String)]| {
+            renames
+                .iter()
+                .map(|(old, new)| format!("{} -> {}", old, new))
+                .collect()
+        };
+
+        section("Renamed sources", renames(&self.renamed_sources));
+        section("Added sources", self.added_sources.clone());
+        section("Removed sources", self.removed_sources.clone());
+        section("Renamed names", renames(&self.renamed_names));
+        section("Changed sourcesContent", self.changed_contents.clone());
+        section("Added mappings", self.added.iter().map(describe).collect());
+        section(
+            "Removed mappings",
+            self.removed.iter().map(describe).collect(),
+        );
+        section(
+            "Changed mappings",
+            self.changed
+                .iter()
+                .map(|(old, new)| format!("{} => {}", describe(old), describe_target(new)))
+                .collect(),
+        );
+
+        report.push_str(&format!(
+            "{} added, {} removed, {} changed, {} unchanged ({:.2}% of mappings moved)\n",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged,
+            self.moved_percent()
+        ));
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::create_consumer;
+
+    #[test]
+    fn identical_maps() {
+        let map = r#"{"version":3,"sources":["a.js"],"names":["x"],"mappings":"AAAAA,EAAE"}"#;
+        let result = diff(
+            &mut create_consumer(map, None).unwrap(),
+            &mut create_consumer(map, None).unwrap(),
+        );
+        assert!(result.is_empty());
+        assert_eq!(result.unchanged, 2);
+        assert_eq!(result.moved_percent(), 0.0);
+    }
+
+    #[test]
+    fn indexed_map_with_source_root() {
+        // The same mappings and content, once as a basic map and once as the
+        // single section of an indexed map with a sourceRoot.
+        let basic = r#"{
+          "version": 3,
+          "sources": ["/src/a.js"],
+          "sourcesContent": ["one"],
+          "names": [],
+          "mappings": "AAAA,EAAE"
+        }"#;
+        let indexed = r#"{
+          "version": 3,
+          "sections": [{
+            "offset": {"line": 0, "column": 0},
+            "map": {
+              "version": 3,
+              "sourceRoot": "/src/",
+              "sources": ["a.js"],
+              "sourcesContent": ["one"],
+              "names": [],
+              "mappings": "AAAA,EAAE"
+            }
+          }]
+        }"#;
+        let result = diff(
+            &mut create_consumer(basic, None).unwrap(),
+            &mut create_consumer(indexed, None).unwrap(),
+        );
+        assert!(result.is_empty(), "{}", result.to_report());
+        assert_eq!(result.unchanged, 2);
+    }
+
+    #[test]
+    fn reports_changes() {
+        // Old: 1:0 -> a.js:1:0 (x), 1:2 -> a.js:1:2, 1:4 -> a.js:1:4.
+        let old = r#"{
+          "version": 3,
+          "sources": ["a.js"],
+          "sourcesContent": ["one"],
+          "names": ["x"],
+          "mappings": "AAAAA,EAAE,EAAE"
+        }"#;
+        // New: a.js is now b.js and x is now y; 1:2 moved to line 2, 1:4 is
+        // gone and 1:6 is new.
+        let new = r#"{
+          "version": 3,
+          "sources": ["b.js"],
+          "sourcesContent": ["two"],
+          "names": ["y"],
+          "mappings": "AAAAA,EACE,IADE"
+        }"#;
+        let result = diff(
+            &mut create_consumer(old, None).unwrap(),
+            &mut create_consumer(new, None).unwrap(),
+        );
+
+        assert_eq!(
+            result.renamed_sources,
+            vec![("a.js".to_string(), "b.js".to_string())]
+        );
+        assert_eq!(
+            result.renamed_names,
+            vec![("x".to_string(), "y".to_string())]
+        );
+        assert!(result.added_sources.is_empty());
+        assert!(result.removed_sources.is_empty());
+        assert_eq!(result.changed_contents, vec!["b.js".to_string()]);
+        assert_eq!(result.unchanged, 1);
+        assert_eq!(result.changed.len(), 1);
+        assert_eq!(result.removed.len(), 1);
+        assert_eq!(result.added.len(), 1);
+
+        assert_eq!(
+            result.to_report(),
+            "Renamed sources:\n  a.js -> b.js\nRenamed names:\n  x -> y\nChanged sourcesContent:\n  b.js\nAdded mappings:\n  1:6 -> b.js:1:4\nRemoved mappings:\n  1:4 -> a.js:1:4\nChanged mappings:\n  1:2 -> a.js:1:2 => b.js:2:2\n1 added, 1 removed, 1 changed, 1 unchanged (66.67% of mappings moved)\n"
+        );
+    }
+
+    #[test]
+    fn indexed_against_basic() {
+        let basic = r#"{"version":3,"sources":["a.js","b.js"],"names":[],"mappings":"AAAA;ACAA"}"#;
+        let indexed = r#"{
+          "version": 3,
+          "sections": [
+            { "offset": { "line": 0, "column": 0 }, "map": { "version": 3, "sources": ["a.js"], "names": [], "mappings": "AAAA" } },
+            { "offset": { "line": 1, "column": 0 }, "map": { "version": 3, "sources": ["b.js"], "names": [], "mappings": "AAAA,CAAC" } }
+          ]
+        }"#;
+        let result = diff(
+            &mut create_consumer(basic, None).unwrap(),
+            &mut create_consumer(indexed, None).unwrap(),
+        );
+
+        assert_eq!(result.unchanged, 2);
+        assert!(result.changed.is_empty());
+        assert_eq!(result.added.len(), 1);
+        assert_eq!(describe(&result.added[0]), "2:1 -> b.js:1:1");
+    }
+}
@@ -0,0 +1,278 @@
+//!
+//! Attribute the bytes of a generated file to the original sources they came
+//! from, like `source-map-explorer` does.
+//!
+use crate::consumer::BasicConsumer;
+use crate::line_index::{ColumnUnit, LineIndex};
+use crate::source_map::Position;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Bucket for code that no mapping (or only an unmapped segment) covers.
+pub const UNMAPPED: &str = "[unmapped]";
+/// Bucket for whitespace and comments, wherever they are.
+pub const WHITESPACE_AND_COMMENTS: &str = "[whitespace and comments]";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SizeReport {
+    pub total_bytes: usize,
+    /// Bytes per original source, plus the two bucket entries.
+    pub files: BTreeMap<String, usize>,
+    /// Bytes per directory of the original sources, summed over everything
+    /// below it. Buckets are not included.
+    pub directories: BTreeMap<String, usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ByteClass {
+    Code,
+    Trivia,
+}
+
+///
+/// Mark every byte of `code` that is whitespace or inside a `//` or `/* */`
+/// comment. String and template literals are skipped so comment markers inside
+/// them don't count; regular expression literals are not recognised.
+///
+fn classify(code: &str) -> Vec<ByteClass> {
+    let bytes = code.as_bytes();
+    let mut classes = vec![ByteClass::Code; bytes.len()];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                classes[i] = ByteClass::Trivia;
+                i += 1;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    classes[i] = ByteClass::Trivia;
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = code[i + 2..]
+                    .find("*/")
+                    .map(|it| i + 2 + it + 2)
+                    .unwrap_or(bytes.len());
+                for class in &mut classes[i..end] {
+                    *class = ByteClass::Trivia;
+                }
+                i = end;
+            }
+            quote @ (b'"' | b'\'' | b'`') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    } else if bytes[i] == b'\n' && quote != b'`' {
+                        break;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    classes
+}
+
+fn add_directories(directories: &mut BTreeMap<String, usize>, source: &str, bytes: usize) {
+    let mut end = 0;
+    while let Some(slash) = source[end..].find('/') {
+        end += slash + 1;
+        let directory = source[..end - 1].trim_end_matches('/');
+        if !directory.is_empty() && !directory.ends_with(':') {
+            *directories.entry(directory.to_string()).or_default() += bytes;
+        }
+    }
+}
+
+///
+/// Attribute every byte of `code` to the original source of the mapping whose
+/// column span covers it. Whitespace and comments go to
+/// `WHITESPACE_AND_COMMENTS` and code outside any mapped span to `UNMAPPED`.
+///
+pub fn attribute_sizes(code: &str, consumer: &mut BasicConsumer) -> SizeReport {
+    let index = LineIndex::new(code);
+    let classes = classify(code);
+    let mut owners: Vec<Option<u32>> = vec![None; code.len()];
+    let mut sources: Vec<String> = vec![];
+
+    let mappings = consumer.mappings_in_generated_range(
+        Position { line: 1, column: 0 },
+        Position {
+            line: index.line_count() as i32 + 1,
+            column: 0,
+        },
+    );
+    for mapping in mappings {
+        let source = match mapping.source {
+            Some(source) if mapping.original.is_some() => source,
+            _ => continue,
+        };
+        let line = mapping.generated.line;
+        let (line_start, line_text) = match (index.line_start(line), index.line(line)) {
+            (Some(start), Some(text)) => (start, text),
+            _ => continue,
+        };
+        let to_byte = |column: i32| {
+            index
+                .convert_column(line, column, ColumnUnit::Utf16, ColumnUnit::Utf8)
+                .unwrap_or(column)
+                .clamp(0, line_text.len() as i32) as usize
+        };
+        let start = line_start + to_byte(mapping.generated.column);
+        let end = line_start
+            + mapping
+                .last_generated_column
+                .map(to_byte)
+                .unwrap_or(line_text.len());
+
+        let owner = match sources.iter().position(|it| *it == source) {
+            Some(owner) => owner,
+            None => {
+                sources.push(source);
+                sources.len() - 1
+            }
+        } as u32;
+        for slot in &mut owners[start..end.max(start)] {
+            *slot = Some(owner);
+        }
+    }
+
+    let mut per_owner = vec![0; sources.len()];
+    let mut unmapped = 0;
+    let mut trivia = 0;
+    for (class, owner) in classes.iter().zip(owners.iter()) {
+        match (class, owner) {
+            (ByteClass::Trivia, _) => trivia += 1,
+            (ByteClass::Code, Some(owner)) => per_owner[*owner as usize] += 1,
+            (ByteClass::Code, None) => unmapped += 1,
+        }
+    }
+
+    let mut files = BTreeMap::new();
+    let mut directories = BTreeMap::new();
+    for (source, bytes) in sources.into_iter().zip(per_owner) {
+        if bytes == 0 {
+            continue;
+        }
+        add_directories(&mut directories, &source, bytes);
+        *files.entry(source).or_default() += bytes;
+    }
+    if unmapped > 0 {
+        files.insert(UNMAPPED.to_string(), unmapped);
+    }
+    if trivia > 0 {
+        files.insert(WHITESPACE_AND_COMMENTS.to_string(), trivia);
+    }
+
+    SizeReport {
+        total_bytes: code.len(),
+        files,
+        directories,
+    }
+}
+
+impl SizeReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Render the per-source breakdown as a plain text table, largest first.
+    pub fn to_table(&self) -> String {
+        let mut rows: Vec<(&String, &usize)> = self.files.iter().collect();
+        rows.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        let width = rows
+            .iter()
+            .map(|it| it.1.to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max("Bytes".len());
+
+        let mut table = format!("{:>width$}  {:>7}  Source\n", "Bytes", "%", width = width);
+        for (source, bytes) in rows {
+            let percent = if self.total_bytes == 0 {
+                0.0
+            } else {
+                *bytes as f64 * 100.0 / self.total_bytes as f64
+            };
+            table.push_str(&format!(
+                "{:>width$}  {:>6.2}%  {}\n",
+                bytes,
+                percent,
+                source,
+                width = width
+            ));
+        }
+        table.push_str(&format!(
+            "{:>width$}  {:>6.2}%  Total\n",
+            self.total_bytes,
+            100.0,
+            width = width
+        ));
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classifies_trivia() {
+        let code = "a /* b */ 'c//' // d\ne";
+        let classes: String = classify(code)
+            .iter()
+            .map(|it| if *it == ByteClass::Trivia { '_' } else { 'x' })
+            .collect();
+        assert_eq!(classes, "x_________xxxxx______x");
+    }
+
+    #[test]
+    fn attributes_bytes() {
+        // `a()` comes from src/a.js, `b()` from src/lib/b.js, `c()` is unmapped.
+        let code = "a();b();c();\n//# sourceMappingURL=x.map";
+        let map = r#"{
+          "version": 3,
+          "sources": ["src/a.js", "src/lib/b.js"],
+          "names": [],
+          "mappings": "AAAA,ICAA,I"
+        }"#;
+        let mut consumer = BasicConsumer::new(map, None);
+        let report = attribute_sizes(code, &mut consumer);
+
+        assert_eq!(report.total_bytes, code.len());
+        assert_eq!(report.files["src/a.js"], 4);
+        assert_eq!(report.files["src/lib/b.js"], 4);
+        assert_eq!(report.files[UNMAPPED], 4);
+        assert_eq!(report.files[WHITESPACE_AND_COMMENTS], 27);
+        assert_eq!(report.files.values().sum::<usize>(), code.len());
+
+        assert_eq!(report.directories["src"], 8);
+        assert_eq!(report.directories["src/lib"], 4);
+
+        let table = report.to_table();
+        assert!(table
+            .starts_with("Bytes        %  Source\n   27   69.23%  [whitespace and comments]\n"));
+        assert!(table.ends_with("   39  100.00%  Total\n"));
+    }
+
+    #[test]
+    fn directories() {
+        let mut directories = BTreeMap::new();
+        add_directories(&mut directories, "webpack:///./src/a.js", 3);
+        add_directories(&mut directories, "/abs/b.js", 2);
+        assert_eq!(
+            directories.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("/abs".to_string(), 2),
+                ("webpack:///.".to_string(), 3),
+                ("webpack:///./src".to_string(), 3),
+            ]
+        );
+    }
+}
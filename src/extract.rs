@@ -0,0 +1,272 @@
+//!
+//! Reconstruct the tree of original sources of a map from its
+//! `sourcesContent`, writing each source under a directory at a path made
+//! from its URL.
+//!
+//! Source URLs come from whoever built the map, so they are sanitized to
+//! stay inside the directory: schemes and Windows drive letters are dropped,
+//! `..` can't climb above the directory, and characters Windows doesn't allow
+//! in file names are replaced with `_`, as are dots and spaces at the end of
+//! a name, which Windows strips. Names Windows reserves for devices, such as
+//! `CON` or `aux.js`, get a `_` in front. The host of a URL is kept as a
+//! directory, so `https://a.com/x.js` is written to `a.com/x.js`.
+//!
+use crate::consumer::Consumer;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    // A URL scheme such as `webpack:`, or a drive letter such as `C:`.
+    static ref SCHEME_REGEXP: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9+.\-]*:").unwrap();
+    // A device name, with or without an extension.
+    static ref RESERVED_REGEXP: Regex =
+        Regex::new(r"(?i)^(con|prn|aux|nul|com[0-9]|lpt[0-9])(\.|$)").unwrap();
+}
+
+/// What `extract_sources` did with each source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Extraction {
+    /// The sources written, with their paths relative to the directory.
+    pub written: Vec<(String, PathBuf)>,
+    /// The sources with no content in the map.
+    pub missing: Vec<String>,
+    /// The sources with content whose URLs name no file, such as `webpack:///`.
+    pub unnamed: Vec<String>,
+    /// The sources not written because an earlier source sanitized to the
+    /// same path, ignoring case, with that path.
+    pub collisions: Vec<(String, PathBuf)>,
+    /// The sources that couldn't be written, with the error.
+    pub failed: Vec<(String, String)>,
+}
+
+///
+/// The path under the extraction directory for the source at `url`, or
+/// `None` if nothing is left of it once sanitized.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::extract::sanitize_path;
+///
+/// let path = |it: &str| sanitize_path(it).map(|it| it.to_string_lossy().replace('\\', "/"));
+/// assert_eq!(path("webpack:///./src/App.vue?5a3b"), Some("src/App.vue_5a3b".to_string()));
+/// assert_eq!(path("/../../etc/passwd"), Some("etc/passwd".to_string()));
+/// assert_eq!(path("C:\\src\\index.ts"), Some("src/index.ts".to_string()));
+/// assert_eq!(path("lib/aux.js"), Some("lib/_aux.js".to_string()));
+/// assert_eq!(path("webpack:///"), None);
+/// ```
+///
+pub fn sanitize_path(url: &str) -> Option<PathBuf> {
+    let path = SCHEME_REGEXP.replace(url, "");
+    let mut components: Vec<String> = vec![];
+    for component in path.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(sanitize_name(component)),
+        }
+    }
+
+    if components.is_empty() {
+        None
+    } else {
+        Some(components.iter().collect())
+    }
+}
+
+/// Make one component of a path a file name Windows accepts as is.
+fn sanitize_name(name: &str) -> String {
+    let kept = name.trim_end_matches(['.', ' ']).len();
+    let mut sanitized: String = name
+        .char_indices()
+        .map(|(i, c)| {
+            if i >= kept || c.is_control() || "<>:\"|?*".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    if RESERVED_REGEXP.is_match(&sanitized) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+///
+/// Write the content of every source of `consumer` under `directory`, at the
+/// sanitized path of its URL as `get_sources` resolves it. Of the sources
+/// whose paths are the same, ignoring case as Windows and macOS do, only the
+/// first one is written. A source that can't be written doesn't stop the
+/// others; only failing to create `directory` itself is an error.
+///
+pub fn extract_sources(consumer: &mut Consumer, directory: &Path) -> io::Result<Extraction> {
+    fs::create_dir_all(directory)?;
+
+    let mut extraction = Extraction::default();
+    let mut paths = HashSet::new();
+    for source in consumer.get_sources() {
+        let content = match consumer.source_content_for(&source, Some(false)) {
+            Some(content) => content,
+            None => {
+                extraction.missing.push(source);
+                continue;
+            }
+        };
+        let path = match sanitize_path(&source) {
+            Some(path) => path,
+            None => {
+                extraction.unnamed.push(source);
+                continue;
+            }
+        };
+
+        if !paths.insert(path.to_string_lossy().to_lowercase()) {
+            extraction.collisions.push((source, path));
+            continue;
+        }
+
+        let full_path = directory.join(&path);
+        let written = match full_path.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| fs::write(&full_path, content));
+        match written {
+            Ok(()) => extraction.written.push((source, path)),
+            Err(error) => extraction.failed.push((source, error.to_string())),
+        }
+    }
+    Ok(extraction)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::create_consumer;
+
+    fn path(url: &str) -> Option<String> {
+        sanitize_path(url).map(|it| it.to_string_lossy().replace('\\', "/"))
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(path("src/a.js"), Some("src/a.js".to_string()));
+        assert_eq!(
+            path("webpack://app/./src/a.js"),
+            Some("app/src/a.js".to_string())
+        );
+        assert_eq!(
+            path("https://example.com/x/../../../a.js"),
+            Some("a.js".to_string())
+        );
+        assert_eq!(path("file:///etc/passwd"), Some("etc/passwd".to_string()));
+        assert_eq!(path("//cdn.com/a.js"), Some("cdn.com/a.js".to_string()));
+        assert_eq!(path("src\\..\\..\\a.js"), Some("a.js".to_string()));
+        assert_eq!(path("D:/a.js"), Some("a.js".to_string()));
+        assert_eq!(
+            path("\\\\server\\share\\a.js"),
+            Some("server/share/a.js".to_string())
+        );
+        assert_eq!(path("a:b\u{0}.js"), Some("b_.js".to_string()));
+        assert_eq!(path("src/a:b.js"), Some("src/a_b.js".to_string()));
+        assert_eq!(path("a.js. ."), Some("a.js___".to_string()));
+        assert_eq!(path("src /a.js"), Some("src_/a.js".to_string()));
+        assert_eq!(path("..."), Some("___".to_string()));
+        assert_eq!(path("CON"), Some("_CON".to_string()));
+        assert_eq!(path("nul.txt"), Some("_nul.txt".to_string()));
+        assert_eq!(path("Com1.tar.gz"), Some("_Com1.tar.gz".to_string()));
+        assert_eq!(path("aux/a.js"), Some("_aux/a.js".to_string()));
+        // Not a device name once the trailing space and dot are replaced.
+        assert_eq!(path("lpt1 ."), Some("lpt1__".to_string()));
+        assert_eq!(path("console.js"), Some("console.js".to_string()));
+        assert_eq!(path("com10.js"), Some("com10.js".to_string()));
+        assert_eq!(path(".."), None);
+        assert_eq!(path(""), None);
+    }
+
+    #[test]
+    fn extract() {
+        let directory =
+            std::env::temp_dir().join(format!("rusty-source-map-extract-{}", std::process::id()));
+        let mut consumer = create_consumer(
+            r#"{
+              "version": 3,
+              "sources": ["webpack:///./src/a.js", "../../etc/b.js", "webpack:///", "c.js"],
+              "sourcesContent": ["a", "b", "nothing"],
+              "names": [],
+              "mappings": ""
+            }"#,
+            None,
+        )
+        .unwrap();
+
+        let extraction = extract_sources(&mut consumer, &directory).unwrap();
+        let written: Vec<_> = extraction
+            .written
+            .iter()
+            .map(|(source, path)| (source.as_str(), path.to_string_lossy().replace('\\', "/")))
+            .collect();
+        assert_eq!(
+            written,
+            vec![
+                ("webpack:///src/a.js", "src/a.js".to_string()),
+                ("../../etc/b.js", "etc/b.js".to_string()),
+            ]
+        );
+        assert_eq!(extraction.unnamed, vec!["webpack:///".to_string()]);
+        assert_eq!(extraction.missing, vec!["c.js".to_string()]);
+        assert_eq!(fs::read_to_string(directory.join("src/a.js")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(directory.join("etc/b.js")).unwrap(), "b");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn conflicting_paths() {
+        let directory = std::env::temp_dir().join(format!(
+            "rusty-source-map-extract-conflicts-{}",
+            std::process::id()
+        ));
+        let mut consumer = create_consumer(
+            r#"{
+              "version": 3,
+              "sources": ["webpack://app/a.js", "https://app/a.js", "https://app/b.js?x", "app/b.js_x", "app/a.js/c.js", "d.js", "APP/A.js"],
+              "sourcesContent": ["a", "a2", "b", "b2", "c", "d", "a3"],
+              "names": [],
+              "mappings": ""
+            }"#,
+            None,
+        )
+        .unwrap();
+
+        let extraction = extract_sources(&mut consumer, &directory).unwrap();
+        let sources = |it: &[(String, PathBuf)]| -> Vec<String> {
+            it.iter().map(|(source, _)| source.clone()).collect()
+        };
+        assert_eq!(
+            sources(&extraction.written),
+            vec!["webpack://app/a.js", "https://app/b.js?x", "d.js"]
+        );
+        assert_eq!(
+            sources(&extraction.collisions),
+            vec!["https://app/a.js", "app/b.js_x", "APP/A.js"]
+        );
+        // `app/a.js` is a file, so nothing can be written under it.
+        assert_eq!(extraction.failed.len(), 1);
+        assert_eq!(extraction.failed[0].0, "app/a.js/c.js");
+        assert_eq!(fs::read_to_string(directory.join("app/a.js")).unwrap(), "a");
+        assert_eq!(
+            fs::read_to_string(directory.join("app/b.js_x")).unwrap(),
+            "b"
+        );
+        assert_eq!(fs::read_to_string(directory.join("d.js")).unwrap(), "d");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+//!
+//! Metro's function maps, carried in `x_facebook_sources`, which tell the
+//! enclosing function of every position in an original source. Metro's
+//! symbolicator names stack frames with them rather than with `names`.
+//!
+//! The `mappings` of a function map have a segment for every place where the
+//! enclosing function changes, in source order. As in `metro-source-map`,
+//! lines are separated by a single `;` however far apart they are, and the
+//! segments of a line by `,`. A segment is a column and an index into the
+//! map's `names`, each a VLQ relative to the previous segment, with the column
+//! starting over at each line. The first segment of a line also has the line
+//! delta, starting from line 1.
+//!
+use crate::base64_vlq::base64vlq_decode;
+use crate::source_map::{FunctionMap, Position};
+use std::convert::TryFrom;
+
+/// Where the function `name` starts to enclose the code of a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionMapping {
+    pub start: Position,
+    pub name: String,
+}
+
+///
+/// Decode `function_map` into the positions where each function starts.
+/// Returns `None` if it is malformed.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::function_map::decode;
+/// use rusty_source_map::source_map::{FunctionMap, Position};
+///
+/// let function_map = FunctionMap {
+///     names: vec!["<global>".to_string(), "foo".to_string()],
+///     mappings: "AAA;aCE,GD".to_string(),
+/// };
+/// let mappings = decode(&function_map).unwrap();
+/// assert_eq!(mappings[1].start, Position { line: 3, column: 13 });
+/// assert_eq!(mappings[1].name, "foo");
+/// assert_eq!(mappings[2].start, Position { line: 3, column: 16 });
+/// assert_eq!(mappings[2].name, "<global>");
+/// ```
+///
+pub fn decode(function_map: &FunctionMap) -> Option<Vec<FunctionMapping>> {
+    let mut mappings = vec![];
+    let mut line: i32 = 1;
+    let mut name: i32 = 0;
+    for segments in function_map.mappings.split(';') {
+        let mut column: i32 = 0;
+        for segment in segments.split(',').filter(|it| !it.is_empty()) {
+            let mut bytes = segment.as_bytes();
+            let mut values = vec![];
+            while !bytes.is_empty() {
+                let (value, length) = base64vlq_decode(bytes)?;
+                values.push(value);
+                bytes = &bytes[length..];
+            }
+
+            match values.len() {
+                2 => {}
+                3 => line = line.checked_add(values[2])?,
+                _ => return None,
+            }
+            column = column.checked_add(values[0])?;
+            name = name.checked_add(values[1])?;
+            mappings.push(FunctionMapping {
+                start: Position { line, column },
+                name: function_map.names.get(usize::try_from(name).ok()?)?.clone(),
+            });
+        }
+    }
+    Some(mappings)
+}
+
+/// The name of the function enclosing `original` among `mappings`, which are
+/// in source order.
+pub fn name_for<'a>(mappings: &'a [FunctionMapping], original: &Position) -> Option<&'a str> {
+    let key = |it: &Position| (it.line, it.column);
+    let index = mappings.partition_point(|it| key(&it.start) <= key(original));
+    Some(mappings[..index].last()?.name.as_str())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        // Metro's function map of:
+        //
+        // function parent() {
+        //   function child() {
+        //   }
+        // }
+        // function parent2() {
+        // }
+        //
+        // after an empty first line.
+        let function_map = FunctionMap {
+            names: vec![
+                "<global>".to_string(),
+                "parent".to_string(),
+                "child".to_string(),
+                "parent2".to_string(),
+            ],
+            mappings: "AAA;ACC;ECC;GDC;CDE;AGC;CHC".to_string(),
+        };
+        let mappings = decode(&function_map).unwrap();
+        let name = |line, column| name_for(&mappings, &Position { line, column });
+
+        assert_eq!(name(1, 0), Some("<global>"));
+        assert_eq!(name(2, 0), Some("parent"));
+        assert_eq!(name(3, 1), Some("parent"));
+        assert_eq!(name(3, 2), Some("child"));
+        assert_eq!(name(4, 2), Some("child"));
+        assert_eq!(name(4, 3), Some("parent"));
+        assert_eq!(name(6, 0), Some("parent"));
+        assert_eq!(name(6, 1), Some("<global>"));
+        assert_eq!(name(7, 10), Some("parent2"));
+        assert_eq!(name(8, 1), Some("<global>"));
+    }
+
+    #[test]
+    fn one_line() {
+        // `foo(function () {});`, where only the first segment of the line
+        // has a line delta.
+        let function_map = FunctionMap {
+            names: vec!["<global>".to_string(), "<anonymous>".to_string()],
+            mappings: "AAA,IC,cD".to_string(),
+        };
+        let mappings = decode(&function_map).unwrap();
+        let name = |column| name_for(&mappings, &Position { line: 1, column });
+
+        assert_eq!(name(3), Some("<global>"));
+        assert_eq!(name(4), Some("<anonymous>"));
+        assert_eq!(name(17), Some("<anonymous>"));
+        assert_eq!(name(18), Some("<global>"));
+    }
+
+    #[test]
+    fn malformed() {
+        let function_map = |mappings: &str| FunctionMap {
+            names: vec!["<global>".to_string()],
+            mappings: mappings.to_string(),
+        };
+        assert_eq!(decode(&function_map("")), Some(vec![]));
+        assert_eq!(decode(&function_map("AC")), None);
+        assert_eq!(decode(&function_map("A")), None);
+        assert_eq!(decode(&function_map("AAAA")), None);
+        assert_eq!(decode(&function_map("g")), None);
+        assert_eq!(decode(&function_map("AA+/////D,AA+/////D")), None);
+        assert_eq!(decode(&function_map("+/////DAA,+/////DA")), None);
+    }
+}
@@ -2,12 +2,17 @@
 
 use crate::array_set::ArraySet;
 use crate::base64_vlq::base64vlq_encode;
+use crate::line_index::{ColumnUnit, LineIndex};
 use crate::mapping::Mapping;
 use crate::mapping_list::MappingList;
+use crate::range_mappings;
+use crate::scopes::{GeneratedRange, OriginalScope, Scopes};
 use crate::source_map::SourceMapJson;
 use crate::util;
+use regex::Regex;
 use serde_json;
 use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
 
 pub struct SourceMapGenerator {
     pub(crate) file: Option<String>,
@@ -18,6 +23,10 @@ pub struct SourceMapGenerator {
     pub(crate) names: ArraySet,
     pub(crate) mappings: MappingList,
     pub(crate) source_contents: HashMap<String, String>,
+    pub(crate) ignored_sources: HashSet<String>,
+    pub(crate) ignore_patterns: Vec<Regex>,
+    pub(crate) debug_id: Option<String>,
+    pub(crate) scopes: Scopes,
 }
 
 impl SourceMapGenerator {
@@ -30,6 +39,10 @@ impl SourceMapGenerator {
             names: ArraySet::new(),
             mappings: MappingList::new(),
             source_contents: HashMap::new(),
+            ignored_sources: HashSet::new(),
+            ignore_patterns: vec![],
+            debug_id: None,
+            scopes: Scopes::default(),
         }
     }
 
@@ -59,6 +72,83 @@ impl SourceMapGenerator {
         self.mappings.add(mapping);
     }
 
+    /// Add a range mapping: the generated code up to the next mapping is a
+    /// verbatim copy of the original, so each following column maps to the
+    /// original column the same distance away. Emitted as `rangeMappings`.
+    pub fn add_range_mapping(&mut self, mapping: Mapping) {
+        self.mappings.mark_range(&mapping.generated);
+        self.add_mapping(mapping);
+    }
+
+    /// Add a mapping whose columns are counted in `unit` rather than in UTF-16
+    /// code units. Generated columns are converted over `generated_text`,
+    /// original columns over `original_text`. Without `original_text` the
+    /// original column must already be in UTF-16 code units. Returns `false`,
+    /// adding nothing, when a line of the mapping is not in its text, as its
+    /// columns then can't be converted.
+    pub fn add_mapping_with_unit(
+        &mut self,
+        mut mapping: Mapping,
+        unit: ColumnUnit,
+        generated_text: &LineIndex,
+        original_text: Option<&LineIndex>,
+    ) -> bool {
+        let convert = |text: &LineIndex, line, column| {
+            text.convert_column(line, column, unit, ColumnUnit::Utf16)
+        };
+
+        let line = mapping.generated.line;
+        mapping.generated.column = match convert(generated_text, line, mapping.generated.column) {
+            Some(column) => column,
+            None => return false,
+        };
+        if let Some(column) = mapping.last_generated_column {
+            // Checked with the generated column above, so the line is there.
+            mapping.last_generated_column = convert(generated_text, line, column);
+        }
+
+        if let (Some(original), Some(original_text)) = (mapping.original.as_mut(), original_text) {
+            original.column = match convert(original_text, original.line, original.column) {
+                Some(column) => column,
+                None => return false,
+            };
+        }
+
+        self.add_mapping(mapping);
+        true
+    }
+
+    /// Add a mapping between two byte offsets: `generated_offset` into
+    /// `generated_text` and `original_offset` into `original_text`, the text of
+    /// `source`. Returns `false`, adding nothing, when either offset is past the
+    /// end of its text.
+    pub fn add_mapping_at_offsets(
+        &mut self,
+        generated_offset: usize,
+        generated_text: &LineIndex,
+        source: &str,
+        original_offset: usize,
+        original_text: &LineIndex,
+        name: Option<String>,
+    ) -> bool {
+        let (generated, original) = match (
+            generated_text.offset_to_position(generated_offset),
+            original_text.offset_to_position(original_offset),
+        ) {
+            (Some(generated), Some(original)) => (generated, original),
+            _ => return false,
+        };
+
+        self.add_mapping(Mapping {
+            generated,
+            original: Some(original),
+            source: Some(source.to_string()),
+            name,
+            last_generated_column: None,
+        });
+        true
+    }
+
     pub fn set_source_content(&mut self, source_file: String, source_content: Option<String>) {
         let mut source = source_file;
         source = match self.source_root {
@@ -73,6 +163,101 @@ impl SourceMapGenerator {
         }
     }
 
+    /// Add `source` to (or remove it from) the `ignoreList`.
+    pub fn set_ignored(&mut self, source: &str, ignored: bool) {
+        if ignored {
+            self.ignored_sources.insert(source.to_string());
+        } else {
+            self.ignored_sources.remove(source);
+        }
+    }
+
+    /// Put every source matching the glob `pattern`, such as
+    /// `node_modules/**`, in the `ignoreList`. See `util::glob_to_regex`.
+    pub fn add_ignore_pattern(&mut self, pattern: &str) {
+        self.ignore_patterns.push(util::glob_to_regex(pattern));
+    }
+
+    fn ignore_list(&self, sources: &[String]) -> Option<Vec<u32>> {
+        let ignored: Vec<u32> = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| {
+                self.ignored_sources.contains(*source)
+                    || self.ignore_patterns.iter().any(|it| it.is_match(source))
+            })
+            .map(|(index, _)| index as u32)
+            .collect();
+        if ignored.is_empty() {
+            None
+        } else {
+            Some(ignored)
+        }
+    }
+
+    pub fn set_debug_id(&mut self, debug_id: Option<String>) {
+        self.debug_id = debug_id;
+    }
+
+    ///
+    /// Set the `debugId` to a UUID derived from the mappings, sources, names
+    /// and source contents, so that the same map always gets the same ID, and
+    /// return it.
+    ///
+    pub fn set_debug_id_from_content(&mut self) -> String {
+        let (mut content, range_mappings) = self.serialize_mappings();
+        if let Some(range_mappings) = range_mappings {
+            content.push('\0');
+            content.push_str(&range_mappings);
+        }
+        for source in self.sources.to_vec() {
+            content.push('\0');
+            content.push_str(&source);
+            content.push('\0');
+            if let Some(source_content) = self.source_contents.get(&source) {
+                content.push_str(source_content);
+            }
+        }
+        for name in self.names.to_vec() {
+            content.push('\0');
+            content.push_str(&name);
+        }
+
+        let debug_id = uuid_from_hash(fnv1a_128(content.as_bytes()));
+        self.debug_id = Some(debug_id.clone());
+        debug_id
+    }
+
+    /// Record the top-level original scopes of `source`, replacing any
+    /// recorded before.
+    pub fn set_original_scopes(&mut self, source: &str, scopes: Vec<OriginalScope>) {
+        if !self.sources.has(source.to_string()) {
+            self.sources.add(source.to_string(), false);
+        }
+        self.scopes.original.insert(source.to_string(), scopes);
+    }
+
+    /// Record a top-level generated range. Its definitions should point at
+    /// scopes recorded with `set_original_scopes`.
+    pub fn add_generated_range(&mut self, range: GeneratedRange) {
+        fn referenced_sources(range: &GeneratedRange, out: &mut Vec<String>) {
+            out.extend(range.definition.iter().map(|it| it.source.clone()));
+            out.extend(range.call_site.iter().map(|it| it.source.clone()));
+            for child in &range.children {
+                referenced_sources(child, out);
+            }
+        }
+
+        let mut sources = vec![];
+        referenced_sources(&range, &mut sources);
+        for source in sources {
+            if !self.sources.has(source.clone()) {
+                self.sources.add(source, false);
+            }
+        }
+        self.scopes.generated.push(range);
+    }
+
     pub fn apply_sourcemap() {
         unimplemented!();
     }
@@ -98,7 +283,9 @@ impl SourceMapGenerator {
         }
     }
 
-    fn serialize_mappings(&mut self) -> String {
+    /// Serialize `mappings`, and `rangeMappings` if any mapping is a range
+    /// mapping.
+    fn serialize_mappings(&mut self) -> (String, Option<String>) {
         let mut previous_generated_column = 0;
         let mut previous_generated_line = 1;
         let mut previous_original_column = 0;
@@ -108,6 +295,10 @@ impl SourceMapGenerator {
         let mut result = "".to_string();
         let mut name_idx;
         let mut source_idx;
+        // The index of the next segment on the line, and the indexes of the
+        // range mappings on each line.
+        let mut segment_idx = 0;
+        let mut range_mappings: Vec<Vec<u32>> = vec![];
 
         let mappings = self.mappings.to_array();
 
@@ -118,6 +309,7 @@ impl SourceMapGenerator {
 
             if mapping.generated.line != previous_generated_line {
                 previous_generated_column = 0;
+                segment_idx = 0;
                 while mapping.generated.line != previous_generated_line {
                     next += ";";
                     previous_generated_line += 1;
@@ -132,6 +324,15 @@ impl SourceMapGenerator {
             next += &base64vlq_encode(mapping.generated.column - previous_generated_column);
             previous_generated_column = mapping.generated.column;
 
+            if self.mappings.is_range(&mapping.generated) {
+                let line = (mapping.generated.line - 1) as usize;
+                if range_mappings.len() <= line {
+                    range_mappings.resize(line + 1, vec![]);
+                }
+                range_mappings[line].push(segment_idx);
+            }
+            segment_idx += 1;
+
             if let Some(mapping_source) = mapping.source {
                 source_idx = self.sources.index_of(mapping_source.clone()).unwrap() as i32;
                 next += &base64vlq_encode(source_idx - previous_source);
@@ -157,7 +358,12 @@ impl SourceMapGenerator {
             result += &next;
         }
 
-        result
+        let range_mappings = if range_mappings.is_empty() {
+            None
+        } else {
+            Some(range_mappings::encode(&range_mappings))
+        };
+        (result, range_mappings)
     }
 
     fn generate_sources_contents(
@@ -183,24 +389,39 @@ impl SourceMapGenerator {
 
     pub(crate) fn as_json(&mut self) -> SourceMapJson {
         let sources_vec = self.sources.to_vec();
-        let mut sources_content: Option<Vec<String>> = None;
+        let ignore_list = self.ignore_list(&sources_vec);
+        let (original_scopes, generated_ranges) = if self.scopes.is_empty() {
+            (None, None)
+        } else {
+            let (original_scopes, generated_ranges) =
+                self.scopes.encode(&sources_vec, &mut self.names);
+            (Some(original_scopes), Some(generated_ranges))
+        };
+        let mut sources_content: Option<Vec<Option<String>>> = None;
         if !self.source_contents.is_empty() {
-            sources_content = Some(
-                self.generate_sources_contents(sources_vec.clone(), self.source_root.clone())
-                    .into_iter()
-                    .flatten()
-                    .collect(),
-            );
+            sources_content =
+                Some(self.generate_sources_contents(sources_vec.clone(), self.source_root.clone()));
         }
+        let (mappings, range_mappings) = self.serialize_mappings();
         SourceMapJson {
             version: 3,
             sources: Some(sources_vec),
             names: Some(self.names.to_vec()),
-            mappings: Some(self.serialize_mappings()),
+            mappings: Some(mappings),
             file: self.file.clone(),
             source_root: self.source_root.clone(),
             sources_content,
             sections: None,
+            ignore_list,
+            x_google_ignore_list: None,
+            debug_id: self.debug_id.clone(),
+            original_scopes,
+            generated_ranges,
+            range_mappings,
+            x_facebook_sources: None,
+            x_facebook_offsets: None,
+            x_metro_module_paths: None,
+            x_hermes_function_offsets: None,
         }
     }
 
@@ -209,6 +430,40 @@ impl SourceMapGenerator {
     }
 }
 
+/// 128-bit FNV-1a. Not cryptographic, but stable across platforms and
+/// releases, which is what a content-derived ID needs.
+fn fnv1a_128(bytes: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u128).wrapping_mul(PRIME)
+    })
+}
+
+/// Format `hash` as a lowercase UUID with the version 4 and RFC 4122 variant
+/// bits set, the form debug IDs take.
+fn uuid_from_hash(hash: u128) -> String {
+    let mut bytes = hash.to_be_bytes();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = |range: std::ops::Range<usize>| -> String {
+        bytes[range]
+            .iter()
+            .map(|it| format!("{:02x}", it))
+            .collect()
+    };
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex(0..4),
+        hex(4..6),
+        hex(6..8),
+        hex(8..10),
+        hex(10..16)
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -226,6 +481,10 @@ mod test {
             names: ArraySet::new(),
             mappings: MappingList::new(),
             source_contents: Default::default(),
+            ignored_sources: Default::default(),
+            ignore_patterns: Default::default(),
+            debug_id: None,
+            scopes: Default::default(),
         }
         .as_json();
         assert!(map.file.is_some());
@@ -242,6 +501,10 @@ mod test {
             names: ArraySet::new(),
             mappings: MappingList::new(),
             source_contents: Default::default(),
+            ignored_sources: Default::default(),
+            ignore_patterns: Default::default(),
+            debug_id: None,
+            scopes: Default::default(),
         }
         .as_string();
         assert_eq!(map, r#"{"version":3,"sources":[],"names":[],"mappings":"","file":"foo.js","sourceRoot":"."}"#.to_string());
@@ -257,6 +520,10 @@ mod test {
             names: ArraySet::new(),
             mappings: MappingList::new(),
             source_contents: Default::default(),
+            ignored_sources: Default::default(),
+            ignore_patterns: Default::default(),
+            debug_id: None,
+            scopes: Default::default(),
         };
 
         map.add_mapping(Mapping {
@@ -407,4 +674,101 @@ mod test {
 
         assert_eq!(map.as_string(), r#"{"version":3,"sources":["one.js","two.js"],"names":["bar","baz","n"],"mappings":"CAAC,IAAI,IAAM,SAAUA,GAClB,OAAOC,IAAID;CCDb,IAAI,IAAM,SAAUE,GAClB,OAAOA","file":"min.js","sourceRoot":"/the/root"}"#.to_string())
     }
+
+    #[test]
+    fn ignore_list() {
+        let mut map = SourceMapGenerator::new(None, None, false);
+        for source in ["src/a.js", "node_modules/b/index.js", "src/c.js"] {
+            map.add_mapping(Mapping {
+                generated: Position { line: 1, column: 0 },
+                original: Some(Position { line: 1, column: 0 }),
+                source: Some(source.to_string()),
+                name: None,
+                last_generated_column: None,
+            });
+        }
+        assert!(map.as_json().ignore_list.is_none());
+
+        map.add_ignore_pattern("node_modules/**");
+        map.set_ignored("src/c.js", true);
+        map.set_ignored("src/a.js", false);
+        assert!(map.as_string().ends_with(r#""ignoreList":[1,2]}"#));
+    }
+
+    #[test]
+    fn missing_sources_content() {
+        let mut map = SourceMapGenerator::new(None, None, false);
+        for source in ["a.js", "b.js"] {
+            map.add_mapping(Mapping {
+                generated: Position { line: 1, column: 0 },
+                original: Some(Position { line: 1, column: 0 }),
+                source: Some(source.to_string()),
+                name: None,
+                last_generated_column: None,
+            });
+        }
+        map.set_source_content("b.js".to_string(), Some("B".to_string()));
+
+        assert_eq!(
+            map.as_json().sources_content,
+            Some(vec![None, Some("B".to_string())])
+        );
+        assert!(map.as_string().contains(r#""sourcesContent":[null,"B"]"#));
+    }
+
+    #[test]
+    fn debug_id_from_content() {
+        let build = |column| {
+            let mut map = SourceMapGenerator::new(Some("a.js".to_string()), None, false);
+            map.add_mapping(Mapping {
+                generated: Position { line: 1, column },
+                original: Some(Position { line: 1, column: 0 }),
+                source: Some("a.ts".to_string()),
+                name: None,
+                last_generated_column: None,
+            });
+            map
+        };
+
+        let mut map = build(0);
+        let debug_id = map.set_debug_id_from_content();
+        assert_eq!(debug_id, build(0).set_debug_id_from_content());
+        assert_ne!(debug_id, build(1).set_debug_id_from_content());
+
+        assert_eq!(debug_id.len(), 36);
+        assert_eq!(&debug_id[14..15], "4");
+        assert!(["8", "9", "a", "b"].contains(&&debug_id[19..20]));
+        assert!(map
+            .as_string()
+            .ends_with(&format!(r#""debugId":"{}"}}"#, debug_id)));
+    }
+
+    #[test]
+    fn range_mappings() {
+        let mut map = SourceMapGenerator::new(None, None, false);
+        for (line, column, is_range) in [(1, 0, false), (1, 4, true), (1, 4, true), (3, 2, true)] {
+            let mapping = Mapping {
+                generated: Position { line, column },
+                original: Some(Position { line, column }),
+                source: Some("a.js".to_string()),
+                name: None,
+                last_generated_column: None,
+            };
+            if is_range {
+                map.add_range_mapping(mapping);
+            } else {
+                map.add_mapping(mapping);
+            }
+        }
+
+        let json = map.as_json();
+        assert_eq!(json.mappings.as_deref(), Some("AAAA,IAAI;;EAEF"));
+        assert_eq!(json.range_mappings.as_deref(), Some("C;;A"));
+    }
+
+    #[test]
+    fn fnv1a() {
+        assert_eq!(fnv1a_128(b""), 0x6c62272e07bb014262b821756295c58d);
+        assert_eq!(fnv1a_128(b"a"), 0xd228cb696f1a8caf78912b704e4a8964);
+    }
 }
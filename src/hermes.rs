@@ -0,0 +1,319 @@
+//!
+//! Symbolication of Hermes stack traces.
+//!
+//! Hermes' maps put each bytecode segment on a generated line of its own (the
+//! segment ID plus one) and use the virtual offset of the bytecode in the
+//! segment as the generated column. Stack traces show frames running bytecode
+//! as `at name (address at file:line:offset)`. Crash dumps give a function ID
+//! and an offset into the function instead, which
+//! `x_hermes_function_offsets` turns into a virtual offset: it has the offset
+//! of each function's bytecode, by segment ID.
+//!
+use crate::consumer::{create_consumer, Consumer};
+use crate::source_map::Position;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+lazy_static! {
+    // `at name (address at file:1:23)`, `at name (file:1:23)` or `at file:1:23`.
+    static ref FRAME_REGEXP: Regex =
+        Regex::new(r"^\s*at (?:(.*?) \()?(address at )?([^()]*):(\d+):(\d+)\)?\s*$").unwrap();
+}
+
+/// A frame of a Hermes stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HermesFrame {
+    pub name: Option<String>,
+    pub file: String,
+    /// For bytecode frames the segment line and virtual offset, else the
+    /// line and (0-based) column in `file`.
+    pub generated: Position,
+    /// Whether the frame was running bytecode, shown as `address at`.
+    pub is_bytecode: bool,
+}
+
+///
+/// Parse one line of a Hermes stack trace. Returns `None` for lines that
+/// aren't frames with a valid position, such as `at foo (native)`.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::hermes::parse_frame;
+/// use rusty_source_map::source_map::Position;
+///
+/// let frame = parse_frame("    at render (address at index.android.bundle:1:9034)").unwrap();
+/// assert_eq!(frame.name.as_deref(), Some("render"));
+/// assert_eq!(frame.file, "index.android.bundle");
+/// assert_eq!(frame.generated, Position { line: 1, column: 9034 });
+/// assert!(frame.is_bytecode);
+///
+/// // Columns of source frames are 1-based.
+/// let frame = parse_frame("    at app.js:12:5").unwrap();
+/// assert_eq!(frame.generated, Position { line: 12, column: 4 });
+/// ```
+///
+pub fn parse_frame(line: &str) -> Option<HermesFrame> {
+    let captures = FRAME_REGEXP.captures(line)?;
+    let is_bytecode = captures.get(2).is_some();
+    let line: i32 = captures[4].parse().ok()?;
+    let column: i32 = captures[5].parse().ok()?;
+    if line < 1 {
+        return None;
+    }
+    Some(HermesFrame {
+        name: captures.get(1).map(|it| it.as_str().to_string()),
+        file: captures[3].to_string(),
+        generated: Position {
+            line,
+            column: if is_bytecode {
+                column
+            } else {
+                (column - 1).max(0)
+            },
+        },
+        is_bytecode,
+    })
+}
+
+/// Where a frame comes from in the original sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolicatedFrame {
+    /// The original function name, or the frame's name if there's none.
+    pub name: Option<String>,
+    pub source: String,
+    pub original: Position,
+}
+
+/// A consumer for Hermes maps, see the module documentation.
+pub struct HermesConsumer {
+    consumer: Consumer,
+    function_offsets: BTreeMap<u32, Vec<u32>>,
+    // The map's `file`, the bundle its frames run in.
+    file: Option<String>,
+}
+
+impl HermesConsumer {
+    pub fn new(source_map_raw: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self::from_consumer(create_consumer(source_map_raw, None)?))
+    }
+
+    pub fn from_consumer(consumer: Consumer) -> Self {
+        let source_map = match &consumer {
+            Consumer::BasicConsumer(consumer) => &consumer.source_map,
+            Consumer::IndexedConsumer(consumer) => &consumer.source_map,
+        };
+        let function_offsets = source_map
+            .x_hermes_function_offsets
+            .iter()
+            .flatten()
+            .filter_map(|(segment, offsets)| Some((segment.parse().ok()?, offsets.clone())))
+            .collect();
+        let file = source_map.file.clone();
+        HermesConsumer {
+            consumer,
+            function_offsets,
+            file,
+        }
+    }
+
+    pub fn consumer(&mut self) -> &mut Consumer {
+        &mut self.consumer
+    }
+
+    /// The generated position of the bytecode `bytecode_offset` bytes into
+    /// function `function_id` of segment `segment_id`.
+    pub fn generated_position_for(
+        &self,
+        segment_id: u32,
+        function_id: u32,
+        bytecode_offset: u32,
+    ) -> Option<Position> {
+        let function_offset = self
+            .function_offsets
+            .get(&segment_id)?
+            .get(function_id as usize)?;
+        Some(Position {
+            line: i32::try_from(segment_id).ok()?.checked_add(1)?,
+            column: i32::try_from(function_offset.checked_add(bytecode_offset)?).ok()?,
+        })
+    }
+
+    /// Symbolicate a frame given by function ID and bytecode offset, as in
+    /// Hermes crash dumps.
+    pub fn symbolicate_bytecode(
+        &mut self,
+        segment_id: u32,
+        function_id: u32,
+        bytecode_offset: u32,
+    ) -> Option<SymbolicatedFrame> {
+        let generated = self.generated_position_for(segment_id, function_id, bytecode_offset)?;
+        self.symbolicate(generated, None)
+    }
+
+    /// Symbolicate a frame of a stack trace. Frames that aren't running
+    /// bytecode are only symbolicated if they are in the map's `file`, since
+    /// the map doesn't cover any other code.
+    pub fn symbolicate_frame(&mut self, frame: &HermesFrame) -> Option<SymbolicatedFrame> {
+        if !frame.is_bytecode && !self.is_own_file(&frame.file) {
+            return None;
+        }
+        self.symbolicate(frame.generated.clone(), frame.name.as_deref())
+    }
+
+    /// Whether `file` names the map's `file`, comparing file names only as
+    /// stack traces show bundles by path or URL.
+    fn is_own_file(&self, file: &str) -> bool {
+        let file_name = |it: &str| {
+            let it = it.split(['?', '#']).next().unwrap_or_default();
+            it.rsplit(['/', '\\'])
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        };
+        match &self.file {
+            Some(own) => file_name(own) == file_name(file),
+            None => false,
+        }
+    }
+
+    fn symbolicate(
+        &mut self,
+        generated: Position,
+        name: Option<&str>,
+    ) -> Option<SymbolicatedFrame> {
+        let mapping = self
+            .consumer
+            .original_position_for(generated.clone(), None)?;
+        let name = self
+            .consumer
+            .original_function_name_for(generated)
+            .or_else(|| name.map(|it| it.to_string()));
+        Some(SymbolicatedFrame {
+            name,
+            source: mapping.source?,
+            original: mapping.original?,
+        })
+    }
+
+    ///
+    /// Rewrite every frame of `stack` that maps to an original position as
+    /// `at name (source:line:column)`, with a 1-based column like other
+    /// JavaScript stack traces. Other lines are kept as they are.
+    ///
+    pub fn symbolicate_stack(&mut self, stack: &str) -> String {
+        stack
+            .split('\n')
+            .map(|line| {
+                let frame = match parse_frame(line).and_then(|it| self.symbolicate_frame(&it)) {
+                    Some(frame) => frame,
+                    None => return line.to_string(),
+                };
+                let indent = &line[..line.len() - line.trim_start().len()];
+                let location = format!(
+                    "{}:{}:{}",
+                    frame.source,
+                    frame.original.line,
+                    frame.original.column + 1
+                );
+                match frame.name {
+                    Some(name) => format!("{}at {} ({})", indent, name, location),
+                    None => format!("{}at {}", indent, location),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Segment 0 holds two functions, `global` at offset 0 and `render` at 100.
+    // Offset 0 maps to app.js 1:0, offset 104 to app.js 3:2 and offset 120 to
+    // app.js 4:4. The function map puts lines 3 and on in `render`.
+    const MAP: &str = r#"{
+      "version": 3,
+      "sources": ["app.js"],
+      "names": [],
+      "mappings": "AAAA,wGAEE,gBACE",
+      "x_facebook_sources": [[{"names": ["<global>", "render"], "mappings": "AAA;ACE"}]],
+      "x_hermes_function_offsets": {"0": [0, 100]}
+    }"#;
+
+    #[test]
+    fn bytecode_offsets() {
+        let mut consumer = HermesConsumer::new(MAP).unwrap();
+        assert_eq!(
+            consumer.generated_position_for(0, 1, 20),
+            Some(Position {
+                line: 1,
+                column: 120
+            })
+        );
+        assert_eq!(consumer.generated_position_for(0, 2, 0), None);
+        assert_eq!(consumer.generated_position_for(1, 0, 0), None);
+
+        assert_eq!(
+            consumer.symbolicate_bytecode(0, 1, 4),
+            Some(SymbolicatedFrame {
+                name: Some("render".to_string()),
+                source: "app.js".to_string(),
+                original: Position { line: 3, column: 2 },
+            })
+        );
+    }
+
+    #[test]
+    fn bad_positions() {
+        let mut consumer = HermesConsumer::new(MAP).unwrap();
+        assert_eq!(parse_frame("at f (address at bundle:0:5)"), None);
+        assert_eq!(parse_frame("at f (address at bundle:1:99999999999)"), None);
+        assert_eq!(
+            consumer.symbolicate_stack("at f (address at bundle:0:5)"),
+            "at f (address at bundle:0:5)"
+        );
+
+        assert_eq!(consumer.generated_position_for(0, 1, u32::MAX - 50), None);
+        assert_eq!(consumer.generated_position_for(0, 1, i32::MAX as u32), None);
+        assert_eq!(consumer.symbolicate_bytecode(0, 1, u32::MAX - 50), None);
+        assert_eq!(consumer.symbolicate_bytecode(0, 1, i32::MAX as u32), None);
+    }
+
+    #[test]
+    fn source_frames() {
+        let map = MAP.replacen('{', r#"{"file": "index.android.bundle","#, 1);
+        let mut consumer = HermesConsumer::new(&map).unwrap();
+        let frame = |file: &str| HermesFrame {
+            name: None,
+            file: file.to_string(),
+            generated: Position { line: 1, column: 0 },
+            is_bytecode: false,
+        };
+
+        assert!(consumer
+            .symbolicate_frame(&frame(
+                "http://localhost:8081/index.android.bundle?dev=true"
+            ))
+            .is_some());
+        assert_eq!(consumer.symbolicate_frame(&frame("other.js")), None);
+
+        let mut consumer = HermesConsumer::new(MAP).unwrap();
+        assert_eq!(
+            consumer.symbolicate_frame(&frame("index.android.bundle")),
+            None
+        );
+    }
+
+    #[test]
+    fn stack() {
+        let mut consumer = HermesConsumer::new(MAP).unwrap();
+        let stack = "Error: boom\n    at render (address at index.android.bundle:1:121)\n    at anonymous (address at index.android.bundle:1:0)\n    at apply (native)\n    at other.js:3:1";
+        assert_eq!(
+            consumer.symbolicate_stack(stack),
+            "Error: boom\n    at render (app.js:4:5)\n    at <global> (app.js:1:1)\n    at apply (native)\n    at other.js:3:1"
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//!
+//! Parse source maps that break the spec in ways real-world tools are known
+//! to, repairing what can be repaired and reporting what was changed.
+//!
+use crate::base64_vlq::{base64vlq_decode, base64vlq_encode};
+use crate::source_map::SourceMapJson;
+use serde_json::Value;
+use std::fmt;
+
+/// The prefix some servers put in front of JSON to defeat cross-site script
+/// inclusion.
+const XSSI_PREFIX: &str = ")]}'";
+
+/// A repair made while parsing leniently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The map started with `)]}'`, which was stripped with the rest of its
+    /// line.
+    XssiPrefix,
+    /// `version` was this string rather than a number.
+    StringVersion(String),
+    /// This field was missing or `null`, and is taken to be empty.
+    MissingField(&'static str),
+    /// The entry of `sources` at this index was `null`. Segments pointing at it
+    /// were made unmapped.
+    NullSource(usize),
+    /// The entry of `names` at this index was `null`. Segments pointing at it
+    /// lost their name.
+    NullName(usize),
+    /// The segment at this index of this generated line (1-based) of
+    /// `mappings` is not valid VLQ, has the wrong number of fields or points
+    /// outside `sources` or `names`, and was dropped.
+    InvalidSegment { line: usize, segment: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::XssiPrefix => write!(f, "stripped the XSSI prefix"),
+            Warning::StringVersion(version) => write!(f, "version {:?} is a string", version),
+            Warning::MissingField(field) => write!(f, "{} is missing", field),
+            Warning::NullSource(index) => write!(f, "source {} is null", index),
+            Warning::NullName(index) => write!(f, "name {} is null", index),
+            Warning::InvalidSegment { line, segment } => {
+                write!(f, "dropped invalid segment {} of line {}", segment, line)
+            }
+        }
+    }
+}
+
+///
+/// Parse `raw` leniently: strip an XSSI prefix, accept a string `version`
+/// (as Sass writes it), make segments pointing at a `null` entry of
+/// `sources` unmapped and drop `null` names from the segments that have them,
+/// and drop the segments of `mappings` that can't be used instead of failing.
+/// Returns the map with what was repaired. Sections of indexed maps are
+/// repaired the same way.
+///
+/// `sources` and `names` are lists of strings, so their `null` entries are
+/// left in place as `""`: removing them would shift the indexes that
+/// `sourcesContent`, `ignoreList` and scopes use. No segment points at them.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::lenient::{parse, Warning};
+///
+/// let (map, warnings) = parse(
+///     ")]}'\n{\"version\":\"3\",\"sources\":[null],\"names\":[],\"mappings\":\"AAAA,!!,CAAC\"}",
+/// )
+/// .unwrap();
+/// assert_eq!(map.version, 3);
+/// assert_eq!(map.sources, Some(vec!["".to_string()]));
+/// assert_eq!(map.mappings.as_deref(), Some("A,C"));
+/// assert_eq!(
+///     warnings,
+///     vec![
+///         Warning::XssiPrefix,
+///         Warning::StringVersion("3".to_string()),
+///         Warning::NullSource(0),
+///         Warning::InvalidSegment { line: 1, segment: 1 },
+///     ]
+/// );
+/// ```
+///
+pub fn parse(raw: &str) -> Result<(SourceMapJson, Vec<Warning>), serde_json::Error> {
+    let mut warnings = vec![];
+    let raw = match raw.strip_prefix(XSSI_PREFIX) {
+        Some(rest) => {
+            warnings.push(Warning::XssiPrefix);
+            rest.find('\n').map_or("", |it| &rest[it + 1..])
+        }
+        None => raw,
+    };
+
+    let mut value: Value = serde_json::from_str(raw)?;
+    repair(&mut value, &mut warnings);
+    Ok((serde_json::from_value(value)?, warnings))
+}
+
+/// Replace `null` entries of the array `field` of `map` with `""`, returning
+/// their indexes.
+fn replace_nulls(
+    map: &mut serde_json::Map<String, Value>,
+    field: &str,
+    warning: fn(usize) -> Warning,
+    warnings: &mut Vec<Warning>,
+) -> Vec<usize> {
+    let mut nulls = vec![];
+    if let Some(Value::Array(entries)) = map.get_mut(field) {
+        for (index, entry) in entries.iter_mut().enumerate() {
+            if entry.is_null() {
+                *entry = Value::String(String::new());
+                warnings.push(warning(index));
+                nulls.push(index);
+            }
+        }
+    }
+    nulls
+}
+
+fn repair(map: &mut Value, warnings: &mut Vec<Warning>) {
+    // Anything but an object is left for deserialization to reject.
+    let map = match map.as_object_mut() {
+        Some(map) => map,
+        None => return,
+    };
+
+    if let Some(Value::String(version)) = map.get("version") {
+        if let Ok(number) = version.trim().parse::<i32>() {
+            warnings.push(Warning::StringVersion(version.clone()));
+            map.insert("version".to_string(), number.into());
+        }
+    }
+
+    if let Some(sections) = map.get_mut("sections").and_then(Value::as_array_mut) {
+        for section in sections {
+            if let Some(section_map) = section.get_mut("map") {
+                repair(section_map, warnings);
+            }
+        }
+        return;
+    }
+
+    for field in ["sources", "names", "mappings"] {
+        if map.get(field).is_none_or(Value::is_null) {
+            let empty = match field {
+                "mappings" => Value::String(String::new()),
+                _ => Value::Array(vec![]),
+            };
+            map.insert(field.to_string(), empty);
+            warnings.push(Warning::MissingField(field));
+        }
+    }
+    let null_sources = replace_nulls(map, "sources", Warning::NullSource, warnings);
+    let null_names = replace_nulls(map, "names", Warning::NullName, warnings);
+
+    let count = |field: &str| map.get(field).and_then(Value::as_array).map_or(0, Vec::len);
+    let sources = Entries {
+        count: count("sources"),
+        nulls: null_sources,
+    };
+    let names = Entries {
+        count: count("names"),
+        nulls: null_names,
+    };
+    if let Some(Value::String(mappings)) = map.get_mut("mappings") {
+        *mappings = repair_mappings(mappings, &sources, &names, warnings);
+    }
+}
+
+/// The length of `sources` or `names`, and the indexes of its `null` entries.
+#[derive(Default)]
+struct Entries {
+    count: usize,
+    nulls: Vec<usize>,
+}
+
+impl Entries {
+    fn is_null(&self, index: i64) -> bool {
+        self.nulls.binary_search(&(index as usize)).is_ok()
+    }
+}
+
+fn decode_segment(segment: &str) -> Option<Vec<i64>> {
+    let mut bytes = segment.as_bytes();
+    let mut values = vec![];
+    while !bytes.is_empty() {
+        let (value, length) = base64vlq_decode(bytes)?;
+        values.push(value as i64);
+        bytes = &bytes[length..];
+    }
+    Some(values)
+}
+
+///
+/// Drop the segments of `mappings` that are not valid VLQ, don't have 1, 4
+/// or 5 fields, or whose fields are out of range, re-encoding the segments
+/// after them. The deltas of a segment that decodes but is out of range still
+/// apply to the segments after it, as its producer meant them to. Segments
+/// pointing at a `null` source keep only their column, and those pointing at
+/// a `null` name lose it.
+///
+fn repair_mappings(
+    mappings: &str,
+    sources: &Entries,
+    names: &Entries,
+    warnings: &mut Vec<Warning>,
+) -> String {
+    // The decoded source, original line, original column and name, and the
+    // same for the last segment written.
+    let mut decoded = [0i64; 4];
+    let mut written = [0i64; 4];
+    let limits = [
+        sources.count as i64,
+        i32::MAX as i64 + 1,
+        i32::MAX as i64 + 1,
+        names.count as i64,
+    ];
+
+    let mut result = String::with_capacity(mappings.len());
+    for (line, line_mappings) in mappings.split(';').enumerate() {
+        if line > 0 {
+            result.push(';');
+        }
+        if line_mappings.is_empty() {
+            continue;
+        }
+
+        let mut column = 0i64;
+        let mut written_column = 0i64;
+        let mut first = true;
+        for (index, segment) in line_mappings.split(',').enumerate() {
+            let fields = decode_segment(segment).filter(|it| matches!(it.len(), 1 | 4 | 5));
+            let fields = match fields {
+                Some(fields) => fields,
+                None => {
+                    warnings.push(Warning::InvalidSegment {
+                        line: line + 1,
+                        segment: index,
+                    });
+                    continue;
+                }
+            };
+
+            column += fields[0];
+            let mut candidate = decoded;
+            for (value, delta) in candidate.iter_mut().zip(&fields[1..]) {
+                *value += delta;
+            }
+            let in_range = (0..=i32::MAX as i64).contains(&column)
+                && candidate
+                    .iter()
+                    .zip(&limits)
+                    .take(fields.len() - 1)
+                    .all(|(value, limit)| (0..*limit).contains(value));
+            decoded = candidate;
+            if !in_range {
+                warnings.push(Warning::InvalidSegment {
+                    line: line + 1,
+                    segment: index,
+                });
+                continue;
+            }
+
+            let kept = match fields.len() {
+                4 | 5 if sources.is_null(decoded[0]) => 1,
+                5 if names.is_null(decoded[3]) => 4,
+                len => len,
+            };
+
+            if !first {
+                result.push(',');
+            }
+            first = false;
+            result += &base64vlq_encode((column - written_column) as i32);
+            written_column = column;
+            for (field, value) in decoded.iter().enumerate().take(kept - 1) {
+                result += &base64vlq_encode((value - written[field]) as i32);
+                written[field] = *value;
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn repaired(mappings: &str, sources: usize, names: usize) -> (String, Vec<Warning>) {
+        let entries = |count| Entries {
+            count,
+            nulls: vec![],
+        };
+        let mut warnings = vec![];
+        let result = repair_mappings(mappings, &entries(sources), &entries(names), &mut warnings);
+        (result, warnings)
+    }
+
+    #[test]
+    fn valid_mappings_are_unchanged() {
+        let mappings = "CAAC,IAAI,IAAM,SAAUA,GAClB,OAAOC,IAAID;CCDb,IAAI,IAAM,SAAUE,GAClB,OAAOA";
+        assert_eq!(repaired(mappings, 2, 3), (mappings.to_string(), vec![]));
+    }
+
+    #[test]
+    fn invalid_segments() {
+        let invalid = |line, segment| Warning::InvalidSegment { line, segment };
+
+        // Bad base64, a truncated VLQ, and two and three fields.
+        assert_eq!(
+            repaired("AAAA,C!AA,Cg,CA,CAA,EAAE", 1, 0),
+            (
+                "AAAA,EAAE".to_string(),
+                vec![invalid(1, 1), invalid(1, 2), invalid(1, 3), invalid(1, 4)]
+            )
+        );
+
+        // The second segment points at source 1 and name 1, past the ends of
+        // `sources` and `names`. Its deltas still count for the third.
+        assert_eq!(
+            repaired("AAAAA;ACAAC;ADAAD", 1, 1),
+            ("AAAAA;;AAAAA".to_string(), vec![invalid(2, 0)])
+        );
+
+        // A negative column, then a negative source. The third segment makes
+        // both 0 again.
+        assert_eq!(
+            repaired("D,ADAA,CCAA", 1, 0),
+            ("AAAA".to_string(), vec![invalid(1, 0), invalid(1, 1)])
+        );
+    }
+
+    #[test]
+    fn repairs() {
+        let (map, warnings) = parse(
+            r#"{"version":" 3 ","sources":["a.js",null],"sourcesContent":[null,"b"],"names":null,"mappings":"AAAA,CCAA,CDAA"}"#,
+        )
+        .unwrap();
+        assert_eq!(map.version, 3);
+        assert_eq!(map.sources, Some(vec!["a.js".to_string(), "".to_string()]));
+        // The second segment points at the null source and becomes unmapped.
+        // The third still points at a.js.
+        assert_eq!(map.mappings.as_deref(), Some("AAAA,C,CAAA"));
+        assert_eq!(map.sources_content, Some(vec![None, Some("b".to_string())]));
+        assert_eq!(map.names, Some(vec![]));
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::StringVersion(" 3 ".to_string()),
+                Warning::MissingField("names"),
+                Warning::NullSource(1),
+            ]
+        );
+
+        // The first segment loses its null name, the second keeps "n".
+        let (map, warnings) = parse(
+            r#"{"version":3,"sources":["a.js"],"names":[null,"n"],"mappings":"AAAAA,CAAAC"}"#,
+        )
+        .unwrap();
+        assert_eq!(map.names, Some(vec!["".to_string(), "n".to_string()]));
+        assert_eq!(map.mappings.as_deref(), Some("AAAA,CAAAC"));
+        assert_eq!(warnings, vec![Warning::NullName(0)]);
+
+        let (map, warnings) = parse(
+            r#"{"version":3,"sections":[{"offset":{"line":0,"column":0},"map":{"version":"3","sources":["a.js"],"names":[],"mappings":"AAAA,C"}}]}"#,
+        )
+        .unwrap();
+        let section = &map.sections.unwrap()[0].map;
+        assert_eq!(section.version, 3);
+        assert_eq!(warnings, vec![Warning::StringVersion("3".to_string())]);
+
+        assert!(parse(r#"{"version":"three"}"#).is_err());
+        assert!(parse(")]}'").is_err());
+    }
+}
@@ -2,11 +2,32 @@ pub mod array_set;
 pub mod base64;
 pub mod base64_vlq;
 pub mod binary_search;
+pub mod code_frame;
+pub mod concat;
 pub mod constants;
 pub mod consumer;
+#[cfg(test)]
 mod consumer_test;
+pub mod coverage;
+pub mod cpu_profile;
+pub mod diff;
+pub mod explore;
+pub mod extract;
+pub mod function_map;
 pub mod generator;
+pub mod hermes;
+pub mod lenient;
+pub mod limits;
+pub mod line_index;
+pub mod magic_string;
 pub mod mapping;
 pub mod mapping_list;
+pub mod normalize;
+pub mod range_mappings;
+pub mod scopes;
 pub mod source_map;
+pub mod source_mapping_url;
 pub mod util;
+pub mod visualize;
+pub mod wasm;
+pub mod writer;
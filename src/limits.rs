@@ -0,0 +1,341 @@
+//!
+//! Caps on the size of a source map, for consumers of maps from untrusted
+//! sources. A map over a cap is rejected before its mappings are decoded, so a
+//! crafted map can't make a consumer exhaust memory.
+//!
+//! Caps are opt-in: `create_consumer` applies none. Pass them as
+//! `ParseOptions::limits` to `create_consumer_with_options`.
+//!
+use crate::base64;
+use crate::source_map::SourceMapJson;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// The size of the JSON, in bytes.
+    pub max_json_size: usize,
+    pub max_sources: usize,
+    pub max_names: usize,
+    pub max_sections: usize,
+    /// How deeply `sections` may nest: 0 allows no sections, 1 allows
+    /// sections of maps without sections of their own.
+    pub max_section_depth: usize,
+    /// The number of segments of `mappings`.
+    pub max_mappings: usize,
+    /// The number of generated lines of `mappings`.
+    pub max_lines: usize,
+    pub max_segments_per_line: usize,
+    /// The number of base64 digits of a VLQ value. A 32-bit value takes at
+    /// most 7. Seven digits can still hold a larger value, which decoding the
+    /// mappings rejects.
+    pub max_vlq_digits: usize,
+}
+
+impl Default for Limits {
+    /// Caps that fit maps of large production bundles.
+    fn default() -> Self {
+        Limits {
+            max_json_size: 256 * 1024 * 1024,
+            max_sources: 100_000,
+            max_names: 1_000_000,
+            max_sections: 10_000,
+            max_section_depth: 1,
+            max_mappings: 50_000_000,
+            max_lines: 10_000_000,
+            // Minified bundles put most of their segments on one line.
+            max_segments_per_line: 10_000_000,
+            max_vlq_digits: 7,
+        }
+    }
+}
+
+/// The cap a map goes over. Counts of the sources, names, sections, mappings
+/// and lines are totals over all the sections of an indexed map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    JsonSize(usize),
+    Sources(usize),
+    Names(usize),
+    Sections(usize),
+    SectionDepth(usize),
+    Mappings(usize),
+    Lines(usize),
+    /// A line (1-based, within its map) has more segments than this.
+    SegmentsPerLine {
+        line: usize,
+        limit: usize,
+    },
+    /// A value on a line (1-based, within its map) has more digits than this.
+    VlqDigits {
+        line: usize,
+        limit: usize,
+    },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::JsonSize(limit) => write!(f, "the map is over {} bytes", limit),
+            LimitError::Sources(limit) => write!(f, "the map has over {} sources", limit),
+            LimitError::Names(limit) => write!(f, "the map has over {} names", limit),
+            LimitError::Sections(limit) => write!(f, "the map has over {} sections", limit),
+            LimitError::SectionDepth(limit) => {
+                write!(f, "the sections of the map nest over {} deep", limit)
+            }
+            LimitError::Mappings(limit) => write!(f, "the map has over {} mappings", limit),
+            LimitError::Lines(limit) => write!(f, "the map has over {} lines", limit),
+            LimitError::SegmentsPerLine { line, limit } => {
+                write!(f, "line {} has over {} segments", line, limit)
+            }
+            LimitError::VlqDigits { line, limit } => {
+                write!(f, "a value on line {} has over {} digits", line, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// Running totals over the sections of a map.
+#[derive(Default)]
+struct Totals {
+    sources: usize,
+    names: usize,
+    sections: usize,
+    mappings: usize,
+    lines: usize,
+}
+
+fn add(total: &mut usize, count: usize, limit: usize, error: LimitError) -> Result<(), LimitError> {
+    *total = total.saturating_add(count);
+    if *total > limit {
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Check the size of the JSON of a map, before it is parsed.
+pub fn check_json_size(raw: &str, limits: &Limits) -> Result<(), LimitError> {
+    if raw.len() > limits.max_json_size {
+        return Err(LimitError::JsonSize(limits.max_json_size));
+    }
+    Ok(())
+}
+
+///
+/// Check a parsed map against `limits`. This scans `mappings` without
+/// decoding it, in time linear in its length and constant memory.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::limits::{check, LimitError, Limits};
+/// use rusty_source_map::source_map::SourceMapJson;
+///
+/// let map = SourceMapJson {
+///     version: 3,
+///     mappings: Some(";".repeat(1000)),
+///     ..Default::default()
+/// };
+/// let limits = Limits {
+///     max_lines: 100,
+///     ..Default::default()
+/// };
+/// assert_eq!(check(&map, &limits), Err(LimitError::Lines(100)));
+/// ```
+///
+pub fn check(source_map: &SourceMapJson, limits: &Limits) -> Result<(), LimitError> {
+    check_map(source_map, limits, 0, &mut Totals::default())
+}
+
+fn check_map(
+    source_map: &SourceMapJson,
+    limits: &Limits,
+    depth: usize,
+    totals: &mut Totals,
+) -> Result<(), LimitError> {
+    if let Some(sections) = &source_map.sections {
+        if depth >= limits.max_section_depth {
+            return Err(LimitError::SectionDepth(limits.max_section_depth));
+        }
+        let error = LimitError::Sections(limits.max_sections);
+        add(
+            &mut totals.sections,
+            sections.len(),
+            limits.max_sections,
+            error,
+        )?;
+        for section in sections {
+            check_map(&section.map, limits, depth + 1, totals)?;
+        }
+    }
+
+    let count = source_map.sources.as_ref().map_or(0, Vec::len);
+    let error = LimitError::Sources(limits.max_sources);
+    add(&mut totals.sources, count, limits.max_sources, error)?;
+    let count = source_map.names.as_ref().map_or(0, Vec::len);
+    let error = LimitError::Names(limits.max_names);
+    add(&mut totals.names, count, limits.max_names, error)?;
+
+    match &source_map.mappings {
+        Some(mappings) => check_mappings(mappings, limits, totals),
+        None => Ok(()),
+    }
+}
+
+fn check_mappings(mappings: &str, limits: &Limits, totals: &mut Totals) -> Result<(), LimitError> {
+    let mut line = 1;
+    let mut segments = 0;
+    let mut digits = 0;
+    let mut in_segment = false;
+    add(
+        &mut totals.lines,
+        1,
+        limits.max_lines,
+        LimitError::Lines(limits.max_lines),
+    )?;
+
+    for byte in mappings.bytes() {
+        match byte {
+            b';' => {
+                line += 1;
+                let error = LimitError::Lines(limits.max_lines);
+                add(&mut totals.lines, 1, limits.max_lines, error)?;
+                segments = 0;
+                in_segment = false;
+            }
+            b',' => in_segment = false,
+            _ => {
+                if !in_segment {
+                    in_segment = true;
+                    digits = 0;
+                    segments += 1;
+                    if segments > limits.max_segments_per_line {
+                        return Err(LimitError::SegmentsPerLine {
+                            line,
+                            limit: limits.max_segments_per_line,
+                        });
+                    }
+                    let error = LimitError::Mappings(limits.max_mappings);
+                    add(&mut totals.mappings, 1, limits.max_mappings, error)?;
+                }
+
+                digits += 1;
+                if digits > limits.max_vlq_digits {
+                    return Err(LimitError::VlqDigits {
+                        line,
+                        limit: limits.max_vlq_digits,
+                    });
+                }
+                // Without the continuation bit, the digit ends its value.
+                // Anything that isn't base64 is left for the parser to reject.
+                if base64::decode(byte).is_none_or(|it| it & 0x20 == 0) {
+                    digits = 0;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::source_map::{Position, Section};
+
+    fn map(mappings: &str) -> SourceMapJson {
+        SourceMapJson {
+            version: 3,
+            sources: Some(vec!["a.js".to_string(), "b.js".to_string()]),
+            names: Some(vec!["x".to_string()]),
+            mappings: Some(mappings.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn indexed(maps: Vec<SourceMapJson>) -> SourceMapJson {
+        SourceMapJson {
+            version: 3,
+            sections: Some(
+                maps.into_iter()
+                    .enumerate()
+                    .map(|(line, map)| Section {
+                        offset: Position {
+                            line: line as i32,
+                            column: 0,
+                        },
+                        url: None,
+                        map: Box::new(map),
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mappings() {
+        let limits = Limits {
+            max_mappings: 5,
+            max_lines: 3,
+            max_segments_per_line: 2,
+            ..Default::default()
+        };
+        assert_eq!(check(&map("AAAA,CAAC;AACA;EAAE,CAAC"), &limits), Ok(()));
+        assert_eq!(
+            check(&map("AAAA,CAAC;;EAAE,CAAC,CAAC"), &limits),
+            Err(LimitError::SegmentsPerLine { line: 3, limit: 2 })
+        );
+        assert_eq!(
+            check(&map("AAAA,CAAC;AACA,A;EAAE,A"), &limits),
+            Err(LimitError::Mappings(5))
+        );
+        assert_eq!(check(&map(";;;"), &limits), Err(LimitError::Lines(3)));
+        // Empty segments don't count.
+        assert_eq!(check(&map("A,,,A;;"), &limits), Ok(()));
+    }
+
+    #[test]
+    fn vlq_digits() {
+        let limits = Limits::default();
+        // A 32-bit value takes at most 7 digits.
+        assert_eq!(check(&map("+/////D"), &limits), Ok(()));
+        assert_eq!(
+            check(&map("A;+//////D"), &limits),
+            Err(LimitError::VlqDigits { line: 2, limit: 7 })
+        );
+        // Digits count per value, not per segment.
+        assert_eq!(check(&map("gggggBgggggBAA"), &limits), Ok(()));
+    }
+
+    #[test]
+    fn sections() {
+        let limits = Limits {
+            max_sources: 3,
+            max_sections: 2,
+            ..Default::default()
+        };
+        assert_eq!(check(&indexed(vec![map("AAAA")]), &limits), Ok(()));
+        assert_eq!(
+            check(&indexed(vec![map("AAAA"), map("AAAA")]), &limits),
+            Err(LimitError::Sources(3))
+        );
+        assert_eq!(
+            check(&indexed(vec![Default::default(); 3]), &limits),
+            Err(LimitError::Sections(2))
+        );
+        assert_eq!(
+            check(&indexed(vec![indexed(vec![])]), &limits),
+            Err(LimitError::SectionDepth(1))
+        );
+        assert_eq!(
+            check(
+                &indexed(vec![]),
+                &Limits {
+                    max_section_depth: 0,
+                    ..Default::default()
+                }
+            ),
+            Err(LimitError::SectionDepth(0))
+        );
+    }
+}
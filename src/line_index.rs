@@ -0,0 +1,480 @@
+use crate::source_map::Position;
+
+/// The unit a column is counted in.
+///
+/// Source maps count columns in UTF-16 code units, which is what JavaScript
+/// engines report. Rust tooling usually works in UTF-8 byte offsets, and some
+/// editors count Unicode scalar values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnUnit {
+    /// UTF-8 bytes from the start of the line.
+    Utf8,
+    /// UTF-16 code units from the start of the line. This is the unit used by
+    /// the source map spec.
+    Utf16,
+    /// Unicode scalar values (`char`s) from the start of the line.
+    CodePoint,
+}
+
+impl ColumnUnit {
+    fn len_of(&self, c: char) -> usize {
+        match self {
+            ColumnUnit::Utf8 => c.len_utf8(),
+            ColumnUnit::Utf16 => c.len_utf16(),
+            ColumnUnit::CodePoint => 1,
+        }
+    }
+}
+
+/// A non-ASCII character, where the units diverge.
+#[derive(Debug, Clone)]
+struct WideChar {
+    c: char,
+    // Byte offset of the character, and the UTF-16 code units and characters
+    // before it in the text.
+    utf8: usize,
+    utf16: usize,
+    chars: usize,
+}
+
+impl WideChar {
+    fn start(&self, unit: ColumnUnit) -> usize {
+        match unit {
+            ColumnUnit::Utf8 => self.utf8,
+            ColumnUnit::Utf16 => self.utf16,
+            ColumnUnit::CodePoint => self.chars,
+        }
+    }
+
+    fn end(&self, unit: ColumnUnit) -> usize {
+        self.start(unit) + unit.len_of(self.c)
+    }
+}
+
+/// Line table over a piece of text, used to translate columns between units
+/// and byte offsets to positions.
+///
+/// Lines are 1-based and columns are 0-based, the same as everywhere else in
+/// this crate. `\n`, `\r\n` and a lone `\r` all end a line.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    text: String,
+    // Byte offset at which every line starts.
+    line_starts: Vec<usize>,
+    // Every non-ASCII character, in order, so that converting a column is a
+    // binary search rather than a walk over its line.
+    wide_chars: Vec<WideChar>,
+}
+
+impl LineIndex {
+    /// # Examples
+    /// ```
+    /// use rusty_source_map::line_index::LineIndex;
+    /// let index = LineIndex::new("a\nb\r\nc");
+    ///
+    /// assert_eq!(index.line_count(), 3);
+    /// assert_eq!(index.line(2), Some("b"));
+    /// ```
+    pub fn new(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let mut line_starts = vec![0];
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\n' => line_starts.push(i + 1),
+                b'\r' => {
+                    if bytes.get(i + 1) == Some(&b'\n') {
+                        i += 1;
+                    }
+                    line_starts.push(i + 1);
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        let mut wide_chars = vec![];
+        let (mut utf16, mut chars) = (0, 0);
+        for (utf8, c) in text.char_indices() {
+            if !c.is_ascii() {
+                wide_chars.push(WideChar {
+                    c,
+                    utf8,
+                    utf16,
+                    chars,
+                });
+            }
+            utf16 += c.len_utf16();
+            chars += 1;
+        }
+
+        LineIndex {
+            text: text.to_string(),
+            line_starts,
+            wide_chars,
+        }
+    }
+
+    /// The text this index was built from.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The content of the given 1-based line, without its terminator.
+    pub fn line(&self, line: i32) -> Option<&str> {
+        if line < 1 || line as usize > self.line_starts.len() {
+            return None;
+        }
+        let start = self.line_starts[line as usize - 1];
+        let end = match self.line_starts.get(line as usize) {
+            Some(&next) => next,
+            None => self.text.len(),
+        };
+        Some(self.text[start..end].trim_end_matches(['\n', '\r']))
+    }
+
+    /// Byte offset at which the given 1-based line starts.
+    pub fn line_start(&self, line: i32) -> Option<usize> {
+        if line < 1 {
+            return None;
+        }
+        self.line_starts.get(line as usize - 1).copied()
+    }
+
+    /// The length in `unit` of the text up to `offset`, which is on a character
+    /// boundary.
+    fn measure(&self, offset: usize, unit: ColumnUnit) -> usize {
+        let index = self.wide_chars.partition_point(|it| it.utf8 < offset);
+        match index.checked_sub(1).map(|it| &self.wide_chars[it]) {
+            Some(wide) => wide.end(unit) + (offset - wide.end(ColumnUnit::Utf8)),
+            None => offset,
+        }
+    }
+
+    /// Turn a byte offset into a position whose column is counted in UTF-16
+    /// code units, like source map columns are. Offsets inside a multi-byte
+    /// character resolve to the start of that character; offsets past the end
+    /// of the text resolve to `None`.
+    ///
+    /// # Examples
+    /// ```
+    /// use rusty_source_map::line_index::LineIndex;
+    /// let index = LineIndex::new("a\n😀b");
+    ///
+    /// let position = index.offset_to_position(6).unwrap();
+    /// assert_eq!((position.line, position.column), (2, 2));
+    /// assert_eq!(index.position_to_offset(&position), Some(6));
+    /// ```
+    pub fn offset_to_position(&self, offset: usize) -> Option<Position> {
+        if offset > self.text.len() {
+            return None;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        let column = self.convert_column(
+            line as i32,
+            (offset - start) as i32,
+            ColumnUnit::Utf8,
+            ColumnUnit::Utf16,
+        )?;
+
+        Some(Position {
+            line: line as i32,
+            column,
+        })
+    }
+
+    /// Turn a position whose column is counted in UTF-16 code units back into a
+    /// byte offset. Columns past the end of the line are clamped to it.
+    pub fn position_to_offset(&self, position: &Position) -> Option<usize> {
+        let start = self.line_start(position.line)?;
+        let line_len = self.line(position.line)?.len();
+        let column = self.convert_column(
+            position.line,
+            position.column,
+            ColumnUnit::Utf16,
+            ColumnUnit::Utf8,
+        )?;
+
+        Some(start + (column.max(0) as usize).min(line_len))
+    }
+
+    /// Convert a column on `line` from one unit to another.
+    ///
+    /// A column that points into the middle of a character is rounded down to
+    /// the start of that character. Columns past the end of the line are
+    /// carried over as-is, as if the line were padded with ASCII.
+    ///
+    /// # Examples
+    /// ```
+    /// use rusty_source_map::line_index::{ColumnUnit, LineIndex};
+    /// let index = LineIndex::new("let s = \"😀\"; s");
+    ///
+    /// // `s` after the emoji is at byte 16, UTF-16 unit 14 and char 13.
+    /// assert_eq!(index.convert_column(1, 16, ColumnUnit::Utf8, ColumnUnit::Utf16), Some(14));
+    /// assert_eq!(index.convert_column(1, 14, ColumnUnit::Utf16, ColumnUnit::CodePoint), Some(13));
+    /// ```
+    pub fn convert_column(
+        &self,
+        line: i32,
+        column: i32,
+        from: ColumnUnit,
+        to: ColumnUnit,
+    ) -> Option<i32> {
+        let text = self.line(line)?;
+        if from == to || column < 0 {
+            return Some(column);
+        }
+
+        let start = self.line_starts[line as usize - 1];
+        let end = start + text.len();
+        let wide_chars = &self.wide_chars[self.wide_chars.partition_point(|it| it.utf8 < start)
+            ..self.wide_chars.partition_point(|it| it.utf8 < end)];
+        let (from_base, to_base) = (self.measure(start, from), self.measure(start, to));
+
+        // Everything between wide characters is ASCII, the same length in
+        // every unit.
+        let column = column as usize;
+        let index = wide_chars.partition_point(|it| it.start(from) - from_base <= column);
+        let converted = match index.checked_sub(1).map(|it| &wide_chars[it]) {
+            None => column,
+            Some(wide) if column < wide.end(from) - from_base => wide.start(to) - to_base,
+            Some(wide) => wide.end(to) - to_base + (column - (wide.end(from) - from_base)),
+        };
+        Some(converted as i32)
+    }
+
+    /// Convert an offset from the start of the text from one unit to another,
+    /// such as the UTF-16 offsets V8 reports into byte offsets. An offset that
+    /// points into the middle of a character is rounded down to the start of
+    /// that character; offsets past the end of the text resolve to `None`.
+    ///
+    /// # Examples
+    /// ```
+    /// use rusty_source_map::line_index::{ColumnUnit, LineIndex};
+    /// let index = LineIndex::new("a\n😀b");
+    ///
+    /// assert_eq!(index.convert_offset(4, ColumnUnit::Utf16, ColumnUnit::Utf8), Some(6));
+    /// assert_eq!(index.convert_offset(6, ColumnUnit::Utf16, ColumnUnit::Utf8), None);
+    /// ```
+    pub fn convert_offset(&self, offset: usize, from: ColumnUnit, to: ColumnUnit) -> Option<usize> {
+        if offset > self.measure(self.text.len(), from) {
+            return None;
+        }
+        let index = self
+            .wide_chars
+            .partition_point(|it| it.start(from) <= offset);
+        let converted = match index.checked_sub(1).map(|it| &self.wide_chars[it]) {
+            None => offset,
+            Some(wide) if offset < wide.end(from) => wide.start(to),
+            Some(wide) => wide.end(to) + (offset - wide.end(from)),
+        };
+        Some(converted)
+    }
+
+    /// Convert the column of `position` from one unit to another.
+    pub fn convert_position(
+        &self,
+        position: &Position,
+        from: ColumnUnit,
+        to: ColumnUnit,
+    ) -> Option<Position> {
+        Some(Position {
+            line: position.line,
+            column: self.convert_column(position.line, position.column, from, to)?,
+        })
+    }
+}
+
+/// The position at the end of text laid out piece by piece, such as generated
+/// output as it is written. Lines end where `LineIndex` ends them, even when a
+/// `\r\n` is split across pieces.
+pub(crate) struct Cursor {
+    pub(crate) position: Position,
+    after_cr: bool,
+}
+
+impl Cursor {
+    pub(crate) fn new() -> Self {
+        Cursor {
+            position: Position { line: 1, column: 0 },
+            after_cr: false,
+        }
+    }
+
+    pub(crate) fn advance_char(&mut self, c: char) {
+        match c {
+            '\n' if self.after_cr => {}
+            '\n' | '\r' => {
+                self.position.line += 1;
+                self.position.column = 0;
+            }
+            _ => self.position.column += c.len_utf16() as i32,
+        }
+        self.after_cr = c == '\r';
+    }
+
+    pub(crate) fn advance(&mut self, text: &str) {
+        for c in text.chars() {
+            self.advance_char(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let index = LineIndex::new("one\ntwo\r\nthree\rfour\n");
+        assert_eq!(index.line_count(), 5);
+        assert_eq!(index.line(1), Some("one"));
+        assert_eq!(index.line(2), Some("two"));
+        assert_eq!(index.line(3), Some("three"));
+        assert_eq!(index.line(4), Some("four"));
+        assert_eq!(index.line(5), Some(""));
+        assert_eq!(index.line(6), None);
+        assert_eq!(index.line(0), None);
+    }
+
+    #[test]
+    fn convert_columns() {
+        // "é" is 2 bytes / 1 unit, "😀" is 4 bytes / 2 units.
+        let index = LineIndex::new("x\né😀ab");
+
+        assert_eq!(
+            index.convert_column(2, 6, ColumnUnit::Utf8, ColumnUnit::Utf16),
+            Some(3)
+        );
+        assert_eq!(
+            index.convert_column(2, 3, ColumnUnit::Utf16, ColumnUnit::Utf8),
+            Some(6)
+        );
+        assert_eq!(
+            index.convert_column(2, 3, ColumnUnit::Utf16, ColumnUnit::CodePoint),
+            Some(2)
+        );
+        assert_eq!(
+            index.convert_column(2, 2, ColumnUnit::CodePoint, ColumnUnit::Utf16),
+            Some(3)
+        );
+
+        // The middle of the surrogate pair rounds down to its start.
+        assert_eq!(
+            index.convert_column(2, 2, ColumnUnit::Utf16, ColumnUnit::Utf8),
+            Some(2)
+        );
+
+        // Past the end of the line.
+        assert_eq!(
+            index.convert_column(2, 10, ColumnUnit::Utf16, ColumnUnit::Utf8),
+            Some(13)
+        );
+        assert_eq!(
+            index.convert_column(3, 0, ColumnUnit::Utf16, ColumnUnit::Utf8),
+            None
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        let text = "ab\r\né😀c\n\nd";
+        let index = LineIndex::new(text);
+
+        let expected = [
+            (0, 1, 0),
+            (2, 1, 2),
+            (4, 2, 0),
+            (6, 2, 1),
+            (8, 2, 1),
+            (10, 2, 3),
+            (11, 2, 4),
+            (12, 3, 0),
+            (13, 4, 0),
+            (14, 4, 1),
+        ];
+        for (offset, line, column) in expected {
+            let position = index.offset_to_position(offset).unwrap();
+            assert_eq!(
+                (position.line, position.column),
+                (line, column),
+                "{}",
+                offset
+            );
+        }
+        assert!(index.offset_to_position(15).is_none());
+
+        for offset in [0, 1, 2, 4, 6, 10, 11, 12, 13, 14] {
+            let position = index.offset_to_position(offset).unwrap();
+            assert_eq!(index.position_to_offset(&position), Some(offset));
+        }
+        assert_eq!(
+            index.position_to_offset(&Position { line: 1, column: 9 }),
+            Some(2)
+        );
+        assert_eq!(
+            index.position_to_offset(&Position { line: 5, column: 0 }),
+            None
+        );
+    }
+
+    #[test]
+    fn convert_offsets() {
+        let index = LineIndex::new("a😀b\u{e9}c");
+        let to_byte = |offset| index.convert_offset(offset, ColumnUnit::Utf16, ColumnUnit::Utf8);
+        assert_eq!(to_byte(0), Some(0));
+        assert_eq!(to_byte(1), Some(1));
+        // Inside the surrogate pair.
+        assert_eq!(to_byte(2), Some(1));
+        assert_eq!(to_byte(3), Some(5));
+        assert_eq!(to_byte(4), Some(6));
+        assert_eq!(to_byte(5), Some(8));
+        assert_eq!(to_byte(6), Some(9));
+        assert_eq!(to_byte(7), None);
+        assert_eq!(
+            index.convert_offset(8, ColumnUnit::Utf8, ColumnUnit::CodePoint),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn long_line() {
+        // A minified bundle: one long line with a few wide characters.
+        let text = format!(
+            "{}é{}😀{}",
+            "a".repeat(100_000),
+            "b".repeat(100_000),
+            "c".repeat(10)
+        );
+        let index = LineIndex::new(&text);
+
+        for column in (0..200_013i32).step_by(7) {
+            let expected = if column <= 100_000 {
+                column
+            } else if column <= 200_001 {
+                column + 1
+            } else if column == 200_002 {
+                200_002
+            } else {
+                column + 3
+            };
+            assert_eq!(
+                index.convert_column(1, column, ColumnUnit::Utf16, ColumnUnit::Utf8),
+                Some(expected),
+                "{}",
+                column
+            );
+        }
+        assert_eq!(
+            index.convert_column(1, 200_004, ColumnUnit::Utf8, ColumnUnit::CodePoint),
+            Some(200_001)
+        );
+        let position = index.offset_to_position(200_013).unwrap();
+        assert_eq!((position.line, position.column), (1, 200_010));
+    }
+}
@@ -0,0 +1,427 @@
+//!
+//! Edit a string and get a source map back to the original, like the
+//! `magic-string` NPM package.
+//!
+//! ref: https://github.com/Rich-Harris/magic-string
+//!
+use crate::generator::SourceMapGenerator;
+use crate::line_index::{Cursor, LineIndex};
+use crate::mapping::Mapping;
+use crate::source_map::Position;
+use std::fmt;
+
+/// A run of the original string, in output order. `intro` and `outro` hold
+/// text inserted right before and after it.
+#[derive(Debug, Clone)]
+struct Chunk {
+    start: usize,
+    end: usize,
+    intro: String,
+    outro: String,
+    content: String,
+    edited: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    /// One mapping per character of unedited text.
+    Chars,
+    /// One mapping per token: a run of identifier characters or a single
+    /// punctuation character. Whitespace is not mapped.
+    Tokens,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapOptions {
+    /// Name of the original file, used as the only entry of `sources`.
+    pub source: String,
+    pub file: Option<String>,
+    /// Store the original string as `sourcesContent`.
+    pub include_content: bool,
+    pub granularity: Granularity,
+}
+
+///
+/// A string being edited. All offsets are byte offsets into the original
+/// string and stay valid however the string has been edited since; they must
+/// fall on character boundaries.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::magic_string::{Granularity, MagicString, MapOptions};
+///
+/// let mut s = MagicString::new("var answer = 42;");
+/// s.overwrite(4, 10, "question").prepend("'use strict';\n");
+/// assert_eq!(s.to_string(), "'use strict';\nvar question = 42;");
+///
+/// let map = s
+///     .generate_map(&MapOptions {
+///         source: "answer.js".to_string(),
+///         file: Some("answer.out.js".to_string()),
+///         include_content: true,
+///         granularity: Granularity::Tokens,
+///     })
+///     .as_string();
+/// assert!(map.contains(r#""sources":["answer.js"]"#));
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct MagicString {
+    original: String,
+    intro: String,
+    outro: String,
+    chunks: Vec<Chunk>,
+}
+
+impl MagicString {
+    pub fn new(original: &str) -> Self {
+        MagicString {
+            original: original.to_string(),
+            intro: String::new(),
+            outro: String::new(),
+            chunks: vec![Chunk {
+                start: 0,
+                end: original.len(),
+                intro: String::new(),
+                outro: String::new(),
+                content: original.to_string(),
+                edited: false,
+            }],
+        }
+    }
+
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+
+    /// Make sure a chunk starts or ends at `index`.
+    fn split(&mut self, index: usize) {
+        assert!(
+            index <= self.original.len() && self.original.is_char_boundary(index),
+            "{} is not a character boundary of the original string",
+            index
+        );
+        let position = match self
+            .chunks
+            .iter()
+            .position(|it| it.start < index && index < it.end)
+        {
+            Some(position) => position,
+            None => return,
+        };
+
+        let chunk = &mut self.chunks[position];
+        assert!(
+            !chunk.edited,
+            "Cannot split a chunk that has already been edited ({}..{})",
+            chunk.start, chunk.end
+        );
+        let second = Chunk {
+            start: index,
+            end: chunk.end,
+            intro: String::new(),
+            outro: std::mem::take(&mut chunk.outro),
+            content: self.original[index..chunk.end].to_string(),
+            edited: false,
+        };
+        chunk.end = index;
+        chunk.content = self.original[chunk.start..index].to_string();
+        self.chunks.insert(position + 1, second);
+    }
+
+    /// Split at `start` and `end` and return the positions of the chunks in
+    /// between, in original order.
+    fn range(&mut self, start: usize, end: usize) -> Vec<usize> {
+        assert!(start <= end, "Invalid range {}..{}", start, end);
+        self.split(start);
+        self.split(end);
+
+        let mut positions: Vec<usize> = (0..self.chunks.len())
+            .filter(|it| self.chunks[*it].start >= start && self.chunks[*it].end <= end)
+            .collect();
+        positions.sort_by_key(|it| self.chunks[*it].start);
+        positions
+    }
+
+    /// Replace `start..end` with `content`, which maps to `start`.
+    pub fn overwrite(&mut self, start: usize, end: usize, content: &str) -> &mut Self {
+        assert!(start < end, "Cannot overwrite an empty range");
+        for (i, position) in self.range(start, end).into_iter().enumerate() {
+            let chunk = &mut self.chunks[position];
+            chunk.intro.clear();
+            chunk.outro.clear();
+            chunk.content = if i == 0 {
+                content.to_string()
+            } else {
+                String::new()
+            };
+            chunk.edited = true;
+        }
+        self
+    }
+
+    /// Remove `start..end` along with anything inserted inside it.
+    pub fn remove(&mut self, start: usize, end: usize) -> &mut Self {
+        for position in self.range(start, end) {
+            let chunk = &mut self.chunks[position];
+            chunk.intro.clear();
+            chunk.outro.clear();
+            chunk.content.clear();
+            chunk.edited = true;
+        }
+        self
+    }
+
+    /// Insert `content` at `index`, attached to the text on its left: it moves
+    /// and is removed along with the character before `index`.
+    pub fn append_left(&mut self, index: usize, content: &str) -> &mut Self {
+        self.split(index);
+        match self.chunks.iter_mut().find(|it| it.end == index) {
+            Some(chunk) if index > 0 => chunk.outro.push_str(content),
+            _ => self.intro.push_str(content),
+        }
+        self
+    }
+
+    /// Insert `content` at `index`, attached to the text on its right.
+    pub fn append_right(&mut self, index: usize, content: &str) -> &mut Self {
+        self.split(index);
+        match self.chunks.iter_mut().find(|it| it.start == index) {
+            Some(chunk) if index < self.original.len() => chunk.intro.push_str(content),
+            _ => self.outro.push_str(content),
+        }
+        self
+    }
+
+    /// Move `start..end` to `index`, right before the text that starts there.
+    pub fn move_range(&mut self, start: usize, end: usize, index: usize) -> &mut Self {
+        assert!(
+            index <= start || index >= end,
+            "Cannot move {}..{} into itself",
+            start,
+            end
+        );
+        self.split(index);
+        let moved: Vec<usize> = self.range(start, end);
+        let mut taken: Vec<Chunk> = moved.iter().map(|it| self.chunks[*it].clone()).collect();
+        let mut remaining: Vec<Chunk> = self
+            .chunks
+            .drain(..)
+            .enumerate()
+            .filter(|(i, _)| !moved.contains(i))
+            .map(|(_, it)| it)
+            .collect();
+
+        let at = remaining
+            .iter()
+            .position(|it| it.start == index && index < self.original.len())
+            .unwrap_or(remaining.len());
+        let tail = remaining.split_off(at);
+        remaining.append(&mut taken);
+        remaining.extend(tail);
+        self.chunks = remaining;
+        self
+    }
+
+    /// Insert `content` at the very start of the result.
+    pub fn prepend(&mut self, content: &str) -> &mut Self {
+        self.intro.insert_str(0, content);
+        self
+    }
+
+    /// Insert `content` at the very end of the result.
+    pub fn append(&mut self, content: &str) -> &mut Self {
+        self.outro.push_str(content);
+        self
+    }
+
+    ///
+    /// Build a source map from the current result back to the original
+    /// string. Unedited text is mapped at the configured granularity, and at
+    /// the start of every generated line it spans. Overwritten text maps to the
+    /// start of what it replaced. Inserted text is not mapped.
+    ///
+    pub fn generate_map(&self, options: &MapOptions) -> SourceMapGenerator {
+        let original = LineIndex::new(&self.original);
+        let mut generator = SourceMapGenerator::new(options.file.clone(), None, true);
+        if options.include_content {
+            generator.set_source_content(options.source.clone(), Some(self.original.clone()));
+        }
+
+        let mut generated = Cursor::new();
+        let mut add = |generated: &Position, offset: usize| {
+            generator.add_mapping(Mapping {
+                generated: generated.clone(),
+                original: original.offset_to_position(offset),
+                source: Some(options.source.clone()),
+                name: None,
+                last_generated_column: None,
+            });
+        };
+
+        generated.advance(&self.intro);
+        for chunk in &self.chunks {
+            generated.advance(&chunk.intro);
+            if chunk.edited {
+                if !chunk.content.is_empty() {
+                    add(&generated.position, chunk.start);
+                }
+                generated.advance(&chunk.content);
+            } else {
+                let mut previous: Option<char> = None;
+                for (i, c) in chunk.content.char_indices() {
+                    // Always map the start of the chunk and of every line,
+                    // since what comes before them may be inserted text.
+                    let is_mapped = c != '\n'
+                        && c != '\r'
+                        && (i == 0
+                            || generated.position.column == 0
+                            || options.granularity == Granularity::Chars
+                            || is_token_start(previous, c));
+                    if is_mapped {
+                        add(&generated.position, chunk.start + i);
+                    }
+                    generated.advance_char(c);
+                    previous = Some(c);
+                }
+            }
+            generated.advance(&chunk.outro);
+        }
+
+        generator
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn is_token_start(previous: Option<char>, c: char) -> bool {
+    if c.is_whitespace() {
+        return false;
+    }
+    match previous {
+        Some(previous) if is_identifier_char(c) => !is_identifier_char(previous),
+        _ => true,
+    }
+}
+
+impl fmt::Display for MagicString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.intro)?;
+        for chunk in &self.chunks {
+            f.write_str(&chunk.intro)?;
+            f.write_str(&chunk.content)?;
+            f.write_str(&chunk.outro)?;
+        }
+        f.write_str(&self.outro)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::BasicConsumer;
+
+    fn consumer(s: &MagicString, granularity: Granularity) -> BasicConsumer {
+        let mut generator = s.generate_map(&MapOptions {
+            source: "input.js".to_string(),
+            file: None,
+            include_content: false,
+            granularity,
+        });
+        BasicConsumer::from_source_map(&mut generator, None)
+    }
+
+    fn original_at(consumer: &mut BasicConsumer, line: i32, column: i32) -> Option<(i32, i32)> {
+        let original = consumer
+            .original_position_for(Position { line, column }, None)?
+            .original?;
+        Some((original.line, original.column))
+    }
+
+    #[test]
+    fn edits() {
+        let mut s = MagicString::new("abcdefghij");
+        s.overwrite(2, 4, "XY")
+            .remove(5, 6)
+            .append_left(8, "<")
+            .append_right(8, ">")
+            .prepend("[")
+            .append("]");
+        assert_eq!(s.to_string(), "[abXYegh<>ij]");
+
+        s.move_range(0, 2, 10);
+        assert_eq!(s.to_string(), "[XYegh<>ijab]");
+
+        let mut s = MagicString::new("one two three");
+        s.move_range(8, 13, 0);
+        assert_eq!(s.to_string(), "threeone two ");
+    }
+
+    #[test]
+    fn multi_line_inserts() {
+        let mut s = MagicString::new("foo();\nbar();");
+        s.prepend("import x;\nimport y;\n")
+            .append_left(6, "\n/* between */");
+        assert_eq!(
+            s.to_string(),
+            "import x;\nimport y;\nfoo();\n/* between */\nbar();"
+        );
+
+        let mut consumer = consumer(&s, Granularity::Tokens);
+        assert_eq!(original_at(&mut consumer, 3, 0), Some((1, 0)));
+        assert_eq!(original_at(&mut consumer, 3, 3), Some((1, 3)));
+        assert_eq!(original_at(&mut consumer, 5, 0), Some((2, 0)));
+        assert_eq!(original_at(&mut consumer, 5, 4), Some((2, 4)));
+        assert_eq!(original_at(&mut consumer, 1, 0), None);
+    }
+
+    #[test]
+    fn line_terminators() {
+        // Lone `\r`s and `\r\n`s end lines, also across chunks.
+        let mut s = MagicString::new("a\rb\r\nc");
+        s.prepend("x\r").overwrite(2, 3, "B").overwrite(4, 5, "\n");
+        assert_eq!(s.to_string(), "x\ra\rB\r\nc");
+
+        let mut consumer = consumer(&s, Granularity::Tokens);
+        assert_eq!(original_at(&mut consumer, 2, 0), Some((1, 0)));
+        assert_eq!(original_at(&mut consumer, 3, 0), Some((2, 0)));
+        assert_eq!(original_at(&mut consumer, 4, 0), Some((3, 0)));
+    }
+
+    #[test]
+    fn overwrite_maps_to_start() {
+        let mut s = MagicString::new("let answer = 42;");
+        s.overwrite(4, 10, "question");
+        let mut consumer = consumer(&s, Granularity::Chars);
+        assert_eq!(original_at(&mut consumer, 1, 4), Some((1, 4)));
+        assert_eq!(original_at(&mut consumer, 1, 11), Some((1, 4)));
+        assert_eq!(original_at(&mut consumer, 1, 13), Some((1, 11)));
+    }
+
+    #[test]
+    fn granularity() {
+        let s = MagicString::new("a.bc = 1;");
+        let count = |granularity| {
+            let mut consumer = consumer(&s, granularity);
+            consumer.mappings_in_generated_range(
+                Position { line: 1, column: 0 },
+                Position { line: 2, column: 0 },
+            )
+        };
+        let tokens: Vec<i32> = count(Granularity::Tokens)
+            .iter()
+            .map(|it| it.generated.column)
+            .collect();
+        assert_eq!(tokens, vec![0, 1, 2, 5, 7, 8]);
+        assert_eq!(count(Granularity::Chars).len(), 9);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot split a chunk that has already been edited")]
+    fn split_edited_chunk() {
+        let mut s = MagicString::new("abcdef");
+        s.overwrite(1, 5, "x").remove(2, 3);
+    }
+}
@@ -1,3 +1,156 @@
+use rusty_source_map::consumer::{create_consumer, BasicConsumer, Consumer};
+use rusty_source_map::hermes::HermesConsumer;
+use rusty_source_map::{diff, explore, extract, visualize};
+use std::env;
+use std::fs::read_to_string;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "Usage: rusty-source-map <command> [options]
+
+Commands:
+  diff <old map> <new map>         Compare what two source maps map, mapping by mapping
+  explore <file> [map] [--json]    Break the size of a generated file down by original source
+  extract <map> <directory>        Write the content of each original source under a directory
+  symbolicate <map> [stack]        Map the frames of a Hermes stack trace (default: stdin) to original sources
+  visualize <file> [map]           Print an HTML page showing the mappings of a generated file
+";
+
+fn read(path: &str) -> Result<String, String> {
+    read_to_string(path).map_err(|err| format!("cannot read {}: {}", path, err))
+}
+
+fn read_consumer(path: &str) -> Result<Consumer, String> {
+    create_consumer(&read(path)?, None).map_err(|err| format!("cannot parse {}: {}", path, err))
+}
+
+/// Split `args` into positional arguments and `--flags`.
+fn split_flags(args: &[String]) -> (Vec<&str>, Vec<&str>) {
+    args.iter()
+        .map(String::as_str)
+        .partition(|it| !it.starts_with("--"))
+}
+
+/// Read `<file> [map]` from `positional`, the map defaulting to `<file>.map`.
+fn read_generated(command: &str, positional: &[&str]) -> Result<(String, BasicConsumer), String> {
+    let file = positional
+        .first()
+        .ok_or_else(|| format!("{}: missing <file>", command))?;
+    let map = match positional.get(1) {
+        Some(map) => map.to_string(),
+        None => format!("{}.map", file),
+    };
+
+    let code = read(file)?;
+    match read_consumer(&map)? {
+        Consumer::BasicConsumer(consumer) => Ok((code, consumer)),
+        Consumer::IndexedConsumer(_) => Err(format!(
+            "{}: indexed source maps are not supported",
+            command
+        )),
+    }
+}
+
+fn diff(args: &[String]) -> Result<(), String> {
+    let (positional, _) = split_flags(args);
+    let (old, new) = match positional.as_slice() {
+        [old, new] => (*old, *new),
+        _ => return Err("diff: expected <old map> <new map>".to_string()),
+    };
+
+    let result = diff::diff(&mut read_consumer(old)?, &mut read_consumer(new)?);
+    print!("{}", result.to_report());
+    Ok(())
+}
+
+fn explore(args: &[String]) -> Result<(), String> {
+    let (positional, flags) = split_flags(args);
+    let (code, mut consumer) = read_generated("explore", &positional)?;
+    let report = explore::attribute_sizes(&code, &mut consumer);
+
+    if flags.contains(&"--json") {
+        println!("{}", report.to_json());
+    } else {
+        print!("{}", report.to_table());
+    }
+    Ok(())
+}
+
+fn extract(args: &[String]) -> Result<(), String> {
+    let (positional, _) = split_flags(args);
+    let (map, directory) = match positional.as_slice() {
+        [map, directory] => (*map, *directory),
+        _ => return Err("extract: expected <map> <directory>".to_string()),
+    };
+
+    let extraction = extract::extract_sources(&mut read_consumer(map)?, Path::new(directory))
+        .map_err(|err| format!("cannot write to {}: {}", directory, err))?;
+    for (_, path) in &extraction.written {
+        println!("{}", Path::new(directory).join(path).display());
+    }
+    for source in &extraction.missing {
+        eprintln!("no content: {}", source);
+    }
+    for source in &extraction.unnamed {
+        eprintln!("no file name: {}", source);
+    }
+    for (source, path) in &extraction.collisions {
+        eprintln!(
+            "same path as an earlier source: {} ({})",
+            source,
+            path.display()
+        );
+    }
+    for (source, error) in &extraction.failed {
+        eprintln!("cannot write {}: {}", source, error);
+    }
+    Ok(())
+}
+
+fn symbolicate(args: &[String]) -> Result<(), String> {
+    let (positional, _) = split_flags(args);
+    let map = positional.first().ok_or("symbolicate: missing <map>")?;
+    let stack = match positional.get(1) {
+        Some(path) => read(path)?,
+        None => {
+            let mut stack = String::new();
+            io::stdin()
+                .read_to_string(&mut stack)
+                .map_err(|err| format!("cannot read stdin: {}", err))?;
+            stack
+        }
+    };
+
+    let mut consumer =
+        HermesConsumer::new(&read(map)?).map_err(|err| format!("cannot parse {}: {}", map, err))?;
+    print!("{}", consumer.symbolicate_stack(&stack));
+    Ok(())
+}
+
+fn visualize(args: &[String]) -> Result<(), String> {
+    let (positional, _) = split_flags(args);
+    let (code, mut consumer) = read_generated("visualize", &positional)?;
+    print!("{}", visualize::render_html(&code, &mut consumer));
+    Ok(())
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("diff") => diff(&args[1..]),
+        Some("explore") => explore(&args[1..]),
+        Some("extract") => extract(&args[1..]),
+        Some("symbolicate") => symbolicate(&args[1..]),
+        Some("visualize") => visualize(&args[1..]),
+        _ => {
+            eprint!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 }
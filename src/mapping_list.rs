@@ -1,6 +1,7 @@
 use crate::mapping::Mapping;
 use crate::source_map::Position;
 use crate::util;
+use std::collections::HashSet;
 
 fn generated_position_after(a: &Mapping, b: &Mapping) -> bool {
     let line_a = a.generated.line;
@@ -15,6 +16,8 @@ pub struct MappingList {
     array: Vec<Mapping>,
     sorted: bool,
     last: Option<Mapping>,
+    // Generated (line, column) of every range mapping.
+    ranges: HashSet<(i32, i32)>,
 }
 
 impl Default for MappingList {
@@ -23,6 +26,7 @@ impl Default for MappingList {
             array: Vec::new(),
             sorted: true,
             last: None,
+            ranges: HashSet::new(),
         }
     }
 }
@@ -34,7 +38,7 @@ impl MappingList {
 
     /// Iterate through internal items. This method takes the same arguments that
     /// `Array.prototype.forEach` takes.
-
+    ///
     /// NOTE: The order of the mappings is NOT guaranteed.
     pub fn unsorted_for_each(&self, callback: impl Fn(&Mapping, usize)) {
         for (index, mapping) in self.array.iter().enumerate() {
@@ -64,13 +68,23 @@ impl MappingList {
         }
     }
 
+    /// Mark the mapping at the `generated` position as a range mapping.
+    pub fn mark_range(&mut self, generated: &Position) {
+        self.ranges.insert((generated.line, generated.column));
+    }
+
+    /// Whether the mapping at the `generated` position is a range mapping.
+    pub fn is_range(&self, generated: &Position) -> bool {
+        self.ranges.contains(&(generated.line, generated.column))
+    }
+
     pub fn to_array(&mut self) -> Vec<Mapping> {
         if !self.sorted {
             self.array.sort_by(|a, b| {
                 let cmp = util::compare_by_generated_pos_inflated(a, b);
                 match cmp {
                     d if d > 0 => std::cmp::Ordering::Greater,
-                    d if d == 0 => std::cmp::Ordering::Equal,
+                    0 => std::cmp::Ordering::Equal,
                     _ => std::cmp::Ordering::Less,
                 }
             });
@@ -0,0 +1,390 @@
+//!
+//! Rewrite a source map into a canonical form, so that two maps with the same
+//! mappings serialize to the same bytes.
+//!
+use crate::generator::SourceMapGenerator;
+use crate::mapping::Mapping;
+use crate::scopes::Scopes;
+use crate::source_map::{Position, Section, SourceMapJson};
+use crate::{range_mappings, util};
+use std::fmt;
+
+/// Why a map can't be normalized without losing mappings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NormalizeError {
+    /// The `mappings` are not valid VLQ.
+    InvalidMappings,
+    /// A segment points at this index, past the end of `sources`.
+    SourceOutOfRange(u32),
+    /// A segment points at this index, past the end of `names`.
+    NameOutOfRange(u32),
+}
+
+impl fmt::Display for NormalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormalizeError::InvalidMappings => write!(f, "the map has invalid mappings"),
+            NormalizeError::SourceOutOfRange(index) => {
+                write!(
+                    f,
+                    "a mapping points at source {}, which doesn't exist",
+                    index
+                )
+            }
+            NormalizeError::NameOutOfRange(index) => {
+                write!(f, "a mapping points at name {}, which doesn't exist", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NormalizeError {}
+
+/// Whether `mapping` adds nothing to the `previous` one on the same line: it
+/// points at the same place, or both are unmapped. An unmapped segment at the
+/// start of a line is redundant too. Range mappings never are.
+fn is_redundant((mapping, is_range): &(Mapping, bool), previous: Option<&(Mapping, bool)>) -> bool {
+    if *is_range {
+        return false;
+    }
+    match previous {
+        Some((previous, previous_is_range))
+            if previous.generated.line == mapping.generated.line =>
+        {
+            !previous_is_range
+                && previous.source == mapping.source
+                && previous.original == mapping.original
+                && previous.name == mapping.name
+        }
+        _ => mapping.original.is_none(),
+    }
+}
+
+///
+/// Rewrite `source_map` into canonical form:
+///
+/// - `sourceRoot` is folded into `sources`,
+/// - `sources` (with their `sourcesContent`) and `names` are ordered by first
+///   use in generated order, and unused entries are dropped,
+/// - duplicate and redundant segments are removed,
+/// - `x_google_ignoreList` is folded into `ignoreList`.
+///
+/// The sections of an indexed map are normalized one by one. Maps that encode
+/// the same mappings normalize to the same JSON. Maps with mappings that
+/// can't be decoded are rejected rather than emptied.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::normalize::normalize;
+/// use rusty_source_map::source_map::SourceMapJson;
+///
+/// let map: SourceMapJson = serde_json::from_str(
+///     r#"{"version":3,"sourceRoot":"src","sources":["unused.js","a.js"],"names":["x"],"mappings":"ACAA"}"#,
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     serde_json::to_string(&normalize(&map).unwrap()).unwrap(),
+///     r#"{"version":3,"sources":["src/a.js"],"names":[],"mappings":"AAAA","file":null}"#
+/// );
+/// ```
+///
+pub fn normalize(source_map: &SourceMapJson) -> Result<SourceMapJson, NormalizeError> {
+    if let Some(sections) = &source_map.sections {
+        return Ok(SourceMapJson {
+            version: 3,
+            file: source_map.file.clone(),
+            sections: Some(
+                sections
+                    .iter()
+                    .map(|section| {
+                        Ok(Section {
+                            offset: section.offset.clone(),
+                            map: Box::new(normalize(&section.map)?),
+                            url: section.url.clone(),
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            ..Default::default()
+        });
+    }
+
+    let sources: Vec<String> = source_map
+        .sources
+        .iter()
+        .flatten()
+        .map(|source| match source_map.source_root.as_deref() {
+            Some(root) if !root.is_empty() => util::compute_source_url(Some(root), source, None),
+            _ => source.clone(),
+        })
+        .collect();
+    let names = source_map.names.clone().unwrap_or_default();
+
+    let parsed = source_map_mappings::parse_mappings::<()>(
+        source_map
+            .mappings
+            .as_deref()
+            .unwrap_or_default()
+            .as_bytes(),
+    )
+    .map_err(|_| NormalizeError::InvalidMappings)?;
+    let range_mappings = range_mappings::generated_positions(
+        source_map.range_mappings.as_deref().unwrap_or_default(),
+        parsed.by_generated_location(),
+    );
+    // Every mapping, with whether it is a range mapping.
+    let mut mappings: Vec<(Mapping, bool)> = parsed
+        .by_generated_location()
+        .iter()
+        .map(|it| {
+            let original = it.original.as_ref();
+            let source = match original {
+                Some(original) => Some(
+                    sources
+                        .get(original.source as usize)
+                        .ok_or(NormalizeError::SourceOutOfRange(original.source))?
+                        .clone(),
+                ),
+                None => None,
+            };
+            let name = match original.and_then(|it| it.name) {
+                Some(name) => Some(
+                    names
+                        .get(name as usize)
+                        .ok_or(NormalizeError::NameOutOfRange(name))?
+                        .clone(),
+                ),
+                None => None,
+            };
+            let mapping = Mapping {
+                generated: Position {
+                    line: (it.generated_line + 1) as i32,
+                    column: it.generated_column as i32,
+                },
+                original: original.map(|it| Position {
+                    line: (it.original_line + 1) as i32,
+                    column: it.original_column as i32,
+                }),
+                source,
+                name,
+                last_generated_column: None,
+            };
+            let is_range = range_mappings.contains(&(it.generated_line, it.generated_column));
+            Ok((mapping, is_range))
+        })
+        .collect::<Result<_, _>>()?;
+    // Order by source and name rather than by their indexes, so the order of
+    // `sources` and `names` in the input doesn't matter.
+    mappings.sort_by(|(a, _), (b, _)| util::compare_by_generated_pos_inflated(a, b).cmp(&0));
+
+    let mut generator = SourceMapGenerator::new(source_map.file.clone(), None, true);
+    let mut previous: Option<(Mapping, bool)> = None;
+    for mapping in mappings {
+        if is_redundant(&mapping, previous.as_ref()) {
+            continue;
+        }
+        previous = Some(mapping.clone());
+        let (mapping, is_range) = mapping;
+        if is_range {
+            generator.add_range_mapping(mapping);
+        } else {
+            generator.add_mapping(mapping);
+        }
+    }
+
+    let ignored: Vec<&String> = source_map
+        .ignored_source_indexes()
+        .iter()
+        .filter_map(|it| sources.get(*it as usize))
+        .collect();
+    for source in ignored {
+        generator.set_ignored(source, true);
+    }
+
+    generator.set_debug_id(source_map.debug_id.clone());
+
+    let scopes = Scopes::decode(
+        source_map.original_scopes.as_deref().unwrap_or_default(),
+        source_map.generated_ranges.as_deref().unwrap_or_default(),
+        &sources,
+        &names,
+    );
+    if let Some(scopes) = scopes {
+        for (source, original) in scopes.original {
+            generator.set_original_scopes(&source, original);
+        }
+        for range in scopes.generated {
+            generator.add_generated_range(range);
+        }
+    }
+
+    let mut normalized = generator.as_json();
+    // The index each source of the normalized map had in `source_map`.
+    let old_indexes: Vec<Option<usize>> = normalized
+        .sources
+        .iter()
+        .flatten()
+        .map(|source| sources.iter().position(|it| it == source))
+        .collect();
+    normalized.sources_content = source_map.sources_content.as_ref().map(|contents| {
+        old_indexes
+            .iter()
+            .map(|index| index.and_then(|it| contents.get(it).cloned().flatten()))
+            .collect()
+    });
+    normalized.x_facebook_sources = source_map.x_facebook_sources.as_ref().map(|metadata| {
+        old_indexes
+            .iter()
+            .map(|index| index.and_then(|it| metadata.get(it).cloned().flatten()))
+            .collect()
+    });
+    normalized.x_facebook_offsets = source_map.x_facebook_offsets.clone();
+    normalized.x_metro_module_paths = source_map.x_metro_module_paths.clone();
+    normalized.x_hermes_function_offsets = source_map.x_hermes_function_offsets.clone();
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn normalized(json: &str) -> String {
+        let map: SourceMapJson = serde_json::from_str(json).unwrap();
+        serde_json::to_string(&normalize(&map).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rejects_what_it_cant_keep() {
+        let normalize = |json: &str| normalize(&serde_json::from_str(json).unwrap());
+        assert_eq!(
+            normalize(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"!!!"}"#).err(),
+            Some(NormalizeError::InvalidMappings)
+        );
+        assert_eq!(
+            normalize(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"ACAA"}"#).err(),
+            Some(NormalizeError::SourceOutOfRange(1))
+        );
+        assert_eq!(
+            normalize(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAAA"}"#).err(),
+            Some(NormalizeError::NameOutOfRange(0))
+        );
+        assert_eq!(
+            normalize(
+                r#"{"version":3,"sections":[{"offset":{"line":0,"column":0},"map":{"version":3,"sources":[],"names":[],"mappings":"!"}}]}"#
+            )
+            .err(),
+            Some(NormalizeError::InvalidMappings)
+        );
+    }
+
+    #[test]
+    fn missing_sources_content() {
+        // `b.js` has no content, which stays absent rather than empty.
+        assert_eq!(
+            normalized(
+                r#"{"version":3,"sources":["a.js","b.js"],"sourcesContent":["A"],"names":[],"mappings":"AAAA,CCAA"}"#
+            ),
+            r#"{"version":3,"sources":["a.js","b.js"],"names":[],"mappings":"AAAA,CCAA","file":null,"sourcesContent":["A",null]}"#
+        );
+    }
+
+    #[test]
+    fn equivalent_maps_normalize_the_same() {
+        // The same two mappings, `1:0 -> a.js:1:0 (x)` and `1:4 -> b.js:2:0`,
+        // with sources and names in a different order, an unused source and
+        // name, a sourceRoot, a duplicate segment and a redundant one.
+        let a = normalized(
+            r#"{
+              "version": 3,
+              "sources": ["lib/a.js", "lib/b.js"],
+              "sourcesContent": ["A", "B"],
+              "names": ["x"],
+              "mappings": "AAAAA,ICCA"
+            }"#,
+        );
+        let b = normalized(
+            r#"{
+              "version": 3,
+              "sourceRoot": "lib",
+              "sources": ["b.js", "unused.js", "a.js"],
+              "sourcesContent": ["B", "U", "A"],
+              "names": ["y", "x"],
+              "mappings": "AEAAC,AAAAA,EAAAA,EFCA"
+            }"#,
+        );
+
+        assert_eq!(a, b);
+        assert_eq!(
+            a,
+            r#"{"version":3,"sources":["lib/a.js","lib/b.js"],"names":["x"],"mappings":"AAAAA,ICCA","file":null,"sourcesContent":["A","B"]}"#
+        );
+    }
+
+    #[test]
+    fn drops_redundant_unmapped_segments() {
+        // `A` at the start of the line and the second `C` are unmapped
+        // segments that don't end any mapped span.
+        assert_eq!(
+            normalized(r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"A,CAAA,C,C;A"}"#),
+            r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"CAAA,C","file":null}"#
+        );
+    }
+
+    #[test]
+    fn sections() {
+        let result = normalized(
+            r#"{
+              "version": 3,
+              "sections": [
+                { "offset": { "line": 0, "column": 0 }, "map": { "version": 3, "sources": ["x.js", "a.js"], "names": [], "mappings": "ACAA" } }
+              ]
+            }"#,
+        );
+        assert_eq!(
+            result,
+            r#"{"version":3,"file":null,"sections":[{"offset":{"line":0,"column":0},"map":{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA","file":null},"url":null}]}"#
+        );
+    }
+
+    #[test]
+    fn ignore_list() {
+        assert_eq!(
+            normalized(
+                r#"{"version":3,"sources":["x.js","a.js","b.js"],"names":[],"mappings":"AEAA,CDAA","x_google_ignoreList":[0,2]}"#
+            ),
+            r#"{"version":3,"sources":["b.js","a.js"],"names":[],"mappings":"AAAA,CCAA","file":null,"ignoreList":[0]}"#
+        );
+    }
+
+    #[test]
+    fn scopes() {
+        assert_eq!(
+            normalized(
+                r#"{"version":3,"sources":["x.js","a.js"],"names":["x","f"],"mappings":"ACAA","originalScopes":["","AACC,CA"],"generatedRanges":"ACCA,C"}"#
+            ),
+            r#"{"version":3,"sources":["a.js"],"names":["f"],"mappings":"AAAA","file":null,"originalScopes":["AACA,CA"],"generatedRanges":"ACAA,C"}"#
+        );
+    }
+
+    #[test]
+    fn range_mappings() {
+        // The second and third segments of the first line are redundant, but
+        // the third one is a range mapping.
+        assert_eq!(
+            normalized(
+                r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA,CAAA,CAAA;AAAA","rangeMappings":"E"}"#
+            ),
+            r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA,EAAA;AAAA","file":null,"rangeMappings":"C"}"#
+        );
+    }
+
+    #[test]
+    fn facebook_sources() {
+        assert_eq!(
+            normalized(
+                r#"{"version":3,"sources":["x.js","a.js"],"names":[],"mappings":"ACAA","x_facebook_sources":[null,[{"names":["<global>"],"mappings":"AAA"}]],"x_metro_module_paths":["a.js"]}"#
+            ),
+            r#"{"version":3,"sources":["a.js"],"names":[],"mappings":"AAAA","file":null,"x_facebook_sources":[[{"names":["<global>"],"mappings":"AAA"}]],"x_metro_module_paths":["a.js"]}"#
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//!
+//! The range mappings proposal: `rangeMappings` marks the mappings after
+//! which the generated code is a verbatim copy of the original up to the next
+//! mapping, so columns in between can be interpolated instead of each getting
+//! a segment of their own.
+//!
+//! ref: https://github.com/tc39/ecma426/blob/main/proposals/range-mappings.md
+//!
+//! Like `mappings`, `rangeMappings` has one `;` separated entry per generated
+//! line. Each entry lists the indexes of the range mappings among the segments
+//! of that line as VLQs, each relative to the previous one on the line.
+//!
+use crate::base64_vlq::{base64vlq_decode, base64vlq_encode};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+///
+/// Decode `rangeMappings` into the indexes of the range mappings on each
+/// generated line. Returns `None` if it is malformed.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::range_mappings::decode;
+///
+/// assert_eq!(decode("AC;;E"), Some(vec![vec![0, 1], vec![], vec![2]]));
+/// ```
+///
+pub fn decode(range_mappings: &str) -> Option<Vec<Vec<u32>>> {
+    range_mappings
+        .split(';')
+        .map(|line| {
+            let mut bytes = line.as_bytes();
+            let mut index: i32 = 0;
+            let mut indexes = vec![];
+            while !bytes.is_empty() {
+                let (value, length) = base64vlq_decode(bytes)?;
+                index = index.checked_add(value)?;
+                indexes.push(u32::try_from(index).ok()?);
+                bytes = &bytes[length..];
+            }
+            Some(indexes)
+        })
+        .collect()
+}
+
+/// Encode the indexes of the range mappings on each generated line. Trailing
+/// lines without any are left out.
+pub fn encode(lines: &[Vec<u32>]) -> String {
+    let used = lines
+        .iter()
+        .rposition(|it| !it.is_empty())
+        .map_or(0, |it| it + 1);
+    lines[..used]
+        .iter()
+        .map(|indexes| {
+            let mut previous = 0;
+            let mut line = String::new();
+            for index in indexes {
+                line += &base64vlq_encode(*index as i32 - previous);
+                previous = *index as i32;
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// The generated positions (0-based line, column) of the range mappings among
+/// `mappings`, which are in generated order.
+pub fn generated_positions(
+    range_mappings: &str,
+    mappings: &[source_map_mappings::Mapping],
+) -> HashSet<(u32, u32)> {
+    let lines = decode(range_mappings).unwrap_or_default();
+    let mut positions = HashSet::new();
+    let mut start = 0;
+    for (line, indexes) in lines.iter().enumerate() {
+        let line = line as u32;
+        while start < mappings.len() && mappings[start].generated_line < line {
+            start += 1;
+        }
+        let end = start
+            + mappings[start..]
+                .iter()
+                .take_while(|it| it.generated_line == line)
+                .count();
+        for index in indexes {
+            if let Some(mapping) = mappings[start..end].get(*index as usize) {
+                positions.insert((line, mapping.generated_column));
+            }
+        }
+        start = end;
+    }
+    positions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let lines = vec![vec![2, 3, 10], vec![], vec![0], vec![]];
+        let encoded = encode(&lines);
+        assert_eq!(encoded, "ECO;;A");
+        assert_eq!(decode(&encoded), Some(lines[..3].to_vec()));
+        assert_eq!(encode(&[]), "");
+        assert_eq!(decode("D"), None);
+        assert_eq!(decode("g"), None);
+        assert_eq!(decode("+/////D+/////D"), None);
+    }
+
+    #[test]
+    fn positions() {
+        let mappings = source_map_mappings::parse_mappings::<()>(b"AAAA,EAAE;;CAAC,CAAC").unwrap();
+        let positions = generated_positions("C;;AC", mappings.by_generated_location());
+        let mut positions: Vec<_> = positions.into_iter().collect();
+        positions.sort_unstable();
+        assert_eq!(positions, vec![(0, 2), (2, 1), (2, 2)]);
+    }
+}
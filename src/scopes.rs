@@ -0,0 +1,693 @@
+//!
+//! The scopes proposal: a tree of original scopes (functions, blocks, ...) for
+//! each source, and the ranges of generated code they ended up in, so that
+//! debuggers can recover original function names and variable bindings.
+//!
+//! ref: https://github.com/tc39/ecma426/blob/main/proposals/scopes.md
+//!
+//! This implements the earlier revision of the proposal, with separate
+//! `originalScopes` and `generatedRanges` fields laid out as below. Later
+//! revisions encode both into a single `scopes` field of tagged items, which
+//! is not read.
+//!
+//! Both are VLQ encoded like `mappings`. `originalScopes` has one string per
+//! source, a `,` separated list of scope start and end items in pre-order:
+//!
+//! - start: line (relative to the previous item), column, flags, then the
+//!   name, the kind and the variables as indexes into `names`, each relative
+//!   to the previous one,
+//! - end: line (relative to the previous item), column.
+//!
+//! `generatedRanges` is a single string with `;` between generated lines and
+//! `,` between the range start and end items on a line:
+//!
+//! - start: column (relative to the previous item on the line), flags, the
+//!   definition (source index relative to the previous definition, scope
+//!   index relative to it as well if the source is the same), the call site
+//!   (source index, line, column), then one binding per variable of the
+//!   definition as an index into `names`, or -1 if it isn't available,
+//! - end: column (relative to the previous item on the line).
+//!
+//! Scopes are referenced by their index in the pre-order of their source's
+//! tree.
+//!
+use crate::array_set::ArraySet;
+use crate::base64_vlq::{base64vlq_decode, base64vlq_encode};
+use crate::source_map::Position;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+const ORIGINAL_HAS_NAME: i32 = 0x1;
+const ORIGINAL_HAS_KIND: i32 = 0x2;
+const ORIGINAL_IS_STACK_FRAME: i32 = 0x4;
+
+const GENERATED_HAS_DEFINITION: i32 = 0x1;
+const GENERATED_HAS_CALL_SITE: i32 = 0x2;
+const GENERATED_IS_STACK_FRAME: i32 = 0x4;
+const GENERATED_IS_HIDDEN: i32 = 0x8;
+
+/// A function, block or other scope in an original source.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OriginalScope {
+    pub start: Position,
+    pub end: Position,
+    /// E.g. `function`, `block` or `class`.
+    pub kind: Option<String>,
+    pub name: Option<String>,
+    /// Whether the scope shows up as a frame in stack traces, like a function.
+    pub is_stack_frame: bool,
+    pub variables: Vec<String>,
+    pub children: Vec<OriginalScope>,
+}
+
+/// Points at the `index`th scope, in pre-order, of the tree for `source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeReference {
+    pub source: String,
+    pub index: usize,
+}
+
+/// Where an inlined function was called from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallSite {
+    pub source: String,
+    pub position: Position,
+}
+
+/// A range of generated code and the original scope it comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GeneratedRange {
+    pub start: Position,
+    pub end: Position,
+    pub definition: Option<ScopeReference>,
+    /// Set when the range is the body of an inlined function.
+    pub call_site: Option<CallSite>,
+    pub is_stack_frame: bool,
+    /// Whether debuggers should leave the range out of stack traces.
+    pub is_hidden: bool,
+    /// The generated expression holding each of the definition's `variables`,
+    /// or `None` if the value isn't available here.
+    pub bindings: Vec<Option<String>>,
+    pub children: Vec<GeneratedRange>,
+}
+
+impl GeneratedRange {
+    fn contains(&self, position: &Position) -> bool {
+        let key = |it: &Position| (it.line, it.column);
+        key(&self.start) <= key(position) && key(position) < key(&self.end)
+    }
+}
+
+/// The `originalScopes` and `generatedRanges` of a source map, decoded.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Scopes {
+    /// The top-level scopes of each source that has any.
+    pub original: BTreeMap<String, Vec<OriginalScope>>,
+    pub generated: Vec<GeneratedRange>,
+}
+
+impl Scopes {
+    pub fn is_empty(&self) -> bool {
+        self.original.is_empty() && self.generated.is_empty()
+    }
+
+    ///
+    /// Decode `originalScopes` (one entry per source) and `generatedRanges`.
+    /// Returns `None` if either is malformed.
+    ///
+    /// # Examples
+    /// ```
+    /// use rusty_source_map::scopes::Scopes;
+    /// use rusty_source_map::source_map::Position;
+    ///
+    /// let scopes = Scopes::decode(
+    ///     &["AAOCDE,EC".to_string()],
+    ///     "ACAAE;K",
+    ///     &["a.js".to_string()],
+    ///     &["function".to_string(), "foo".to_string(), "x".to_string()],
+    /// )
+    /// .unwrap();
+    /// let scope = scopes.original_scope_at(&Position { line: 1, column: 3 }).unwrap();
+    /// assert_eq!(scope.name.as_deref(), Some("foo"));
+    /// assert_eq!(scope.variables, vec!["x".to_string()]);
+    /// ```
+    ///
+    pub fn decode(
+        original_scopes: &[String],
+        generated_ranges: &str,
+        sources: &[String],
+        names: &[String],
+    ) -> Option<Scopes> {
+        let mut original = BTreeMap::new();
+        for (source, text) in sources.iter().zip(original_scopes) {
+            let scopes = decode_original_scopes(text, names)?;
+            if !scopes.is_empty() {
+                original.insert(source.clone(), scopes);
+            }
+        }
+        Some(Scopes {
+            original,
+            generated: decode_generated_ranges(generated_ranges, sources, names)?,
+        })
+    }
+
+    ///
+    /// Encode into `originalScopes`, aligned with `sources`, and
+    /// `generatedRanges`. Names are looked up in (and added to) `names`.
+    /// Scopes and definitions of sources missing from `sources` are left out.
+    ///
+    pub fn encode(&self, sources: &[String], names: &mut ArraySet) -> (Vec<String>, String) {
+        let original_scopes = sources
+            .iter()
+            .map(|source| match self.original.get(source) {
+                Some(scopes) => encode_original_scopes(scopes, names),
+                None => String::new(),
+            })
+            .collect();
+        let generated_ranges = encode_generated_ranges(&self.generated, sources, names);
+        (original_scopes, generated_ranges)
+    }
+
+    /// The scope `reference` points at.
+    pub fn original_scope(&self, reference: &ScopeReference) -> Option<&OriginalScope> {
+        let mut scopes = vec![];
+        for scope in self.original.get(&reference.source)? {
+            preorder(scope, &mut scopes);
+        }
+        scopes.get(reference.index).copied()
+    }
+
+    /// The generated ranges containing `generated`, outermost first.
+    pub fn ranges_at(&self, generated: &Position) -> Vec<&GeneratedRange> {
+        let mut chain = vec![];
+        let mut ranges = &self.generated;
+        while let Some(range) = ranges.iter().find(|it| it.contains(generated)) {
+            chain.push(range);
+            ranges = &range.children;
+        }
+        chain
+    }
+
+    /// The innermost original scope `generated` comes from.
+    pub fn original_scope_at(&self, generated: &Position) -> Option<&OriginalScope> {
+        self.ranges_at(generated)
+            .iter()
+            .rev()
+            .find_map(|range| range.definition.as_ref())
+            .and_then(|definition| self.original_scope(definition))
+    }
+
+    /// The name of the innermost original function (stack frame scope)
+    /// `generated` comes from.
+    pub fn original_function_name_at(&self, generated: &Position) -> Option<&str> {
+        self.ranges_at(generated)
+            .iter()
+            .rev()
+            .filter_map(|range| self.original_scope(range.definition.as_ref()?))
+            .find(|scope| scope.is_stack_frame)
+            .and_then(|scope| scope.name.as_deref())
+    }
+}
+
+fn preorder<'a>(scope: &'a OriginalScope, out: &mut Vec<&'a OriginalScope>) {
+    out.push(scope);
+    for child in &scope.children {
+        preorder(child, out);
+    }
+}
+
+fn decode_item(item: &str) -> Option<Vec<i32>> {
+    let mut bytes = item.as_bytes();
+    let mut values = vec![];
+    while !bytes.is_empty() {
+        let (value, length) = base64vlq_decode(bytes)?;
+        values.push(value);
+        bytes = &bytes[length..];
+    }
+    Some(values)
+}
+
+fn encode_item(values: &[i32]) -> String {
+    values.iter().map(|it| base64vlq_encode(*it)).collect()
+}
+
+fn name_index(names: &mut ArraySet, name: &str) -> i32 {
+    if !names.has(name.to_string()) {
+        names.add(name.to_string(), false);
+    }
+    names.index_of(name.to_string()).unwrap() as i32
+}
+
+fn decode_original_scopes(text: &str, names: &[String]) -> Option<Vec<OriginalScope>> {
+    let mut roots = vec![];
+    if text.is_empty() {
+        return Some(roots);
+    }
+
+    let mut stack: Vec<OriginalScope> = vec![];
+    let mut line: i32 = 0;
+    let mut name: i32 = 0;
+    for item in text.split(',') {
+        let values = decode_item(item)?;
+        if values.len() < 2 {
+            return None;
+        }
+        line = line.checked_add(values[0])?;
+        let position = Position {
+            line: line.checked_add(1)?,
+            column: values[1],
+        };
+
+        if values.len() == 2 {
+            let mut scope = stack.pop()?;
+            scope.end = position;
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(scope),
+                None => roots.push(scope),
+            }
+            continue;
+        }
+
+        let flags = values[2];
+        let mut rest = values[3..].iter();
+        let mut next_name = || -> Option<String> {
+            name = name.checked_add(*rest.next()?)?;
+            names.get(usize::try_from(name).ok()?).cloned()
+        };
+        let scope_name = if flags & ORIGINAL_HAS_NAME != 0 {
+            Some(next_name()?)
+        } else {
+            None
+        };
+        let kind = if flags & ORIGINAL_HAS_KIND != 0 {
+            Some(next_name()?)
+        } else {
+            None
+        };
+        let variables = std::iter::from_fn(next_name).collect();
+        stack.push(OriginalScope {
+            start: position,
+            kind,
+            name: scope_name,
+            is_stack_frame: flags & ORIGINAL_IS_STACK_FRAME != 0,
+            variables,
+            ..Default::default()
+        });
+    }
+
+    if stack.is_empty() {
+        Some(roots)
+    } else {
+        None
+    }
+}
+
+fn encode_original_scopes(scopes: &[OriginalScope], names: &mut ArraySet) -> String {
+    fn walk(
+        scope: &OriginalScope,
+        items: &mut Vec<String>,
+        line: &mut i32,
+        name: &mut i32,
+        names: &mut ArraySet,
+    ) {
+        let mut flags = 0;
+        if scope.name.is_some() {
+            flags |= ORIGINAL_HAS_NAME;
+        }
+        if scope.kind.is_some() {
+            flags |= ORIGINAL_HAS_KIND;
+        }
+        if scope.is_stack_frame {
+            flags |= ORIGINAL_IS_STACK_FRAME;
+        }
+
+        let mut values = vec![scope.start.line - 1 - *line, scope.start.column, flags];
+        *line = scope.start.line - 1;
+        let referenced = scope
+            .name
+            .iter()
+            .chain(scope.kind.iter())
+            .chain(scope.variables.iter());
+        for it in referenced {
+            let index = name_index(names, it);
+            values.push(index - *name);
+            *name = index;
+        }
+        items.push(encode_item(&values));
+
+        for child in &scope.children {
+            walk(child, items, line, name, names);
+        }
+
+        items.push(encode_item(&[scope.end.line - 1 - *line, scope.end.column]));
+        *line = scope.end.line - 1;
+    }
+
+    let mut items = vec![];
+    let (mut line, mut name) = (0, 0);
+    for scope in scopes {
+        walk(scope, &mut items, &mut line, &mut name, names);
+    }
+    items.join(",")
+}
+
+fn decode_generated_ranges(
+    text: &str,
+    sources: &[String],
+    names: &[String],
+) -> Option<Vec<GeneratedRange>> {
+    let mut roots = vec![];
+    let mut stack: Vec<GeneratedRange> = vec![];
+    let mut definition_source: i32 = 0;
+    let mut definition_scope: i32 = 0;
+
+    for (line, line_text) in text.split(';').enumerate() {
+        if line_text.is_empty() {
+            continue;
+        }
+        let mut column: i32 = 0;
+        for item in line_text.split(',') {
+            let values = decode_item(item)?;
+            if values.is_empty() {
+                return None;
+            }
+            column = column.checked_add(values[0])?;
+            let position = Position {
+                line: i32::try_from(line).ok()?.checked_add(1)?,
+                column,
+            };
+
+            if values.len() == 1 {
+                let mut range = stack.pop()?;
+                range.end = position;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(range),
+                    None => roots.push(range),
+                }
+                continue;
+            }
+
+            let flags = values[1];
+            let mut rest = values[2..].iter().copied();
+            let source_at = |index: i32| sources.get(usize::try_from(index).ok()?).cloned();
+
+            let definition = if flags & GENERATED_HAS_DEFINITION != 0 {
+                let source_delta = rest.next()?;
+                let scope = rest.next()?;
+                if source_delta != 0 {
+                    definition_source = definition_source.checked_add(source_delta)?;
+                    definition_scope = scope;
+                } else {
+                    definition_scope = definition_scope.checked_add(scope)?;
+                }
+                Some(ScopeReference {
+                    source: source_at(definition_source)?,
+                    index: usize::try_from(definition_scope).ok()?,
+                })
+            } else {
+                None
+            };
+
+            let call_site = if flags & GENERATED_HAS_CALL_SITE != 0 {
+                let source = source_at(rest.next()?)?;
+                let line = rest.next()?;
+                let column = rest.next()?;
+                Some(CallSite {
+                    source,
+                    position: Position {
+                        line: line.checked_add(1)?,
+                        column,
+                    },
+                })
+            } else {
+                None
+            };
+
+            let bindings = rest
+                .map(|index| match index {
+                    -1 => Some(None),
+                    _ => Some(Some(names.get(usize::try_from(index).ok()?)?.clone())),
+                })
+                .collect::<Option<Vec<_>>>()?;
+
+            stack.push(GeneratedRange {
+                start: position,
+                definition,
+                call_site,
+                is_stack_frame: flags & GENERATED_IS_STACK_FRAME != 0,
+                is_hidden: flags & GENERATED_IS_HIDDEN != 0,
+                bindings,
+                ..Default::default()
+            });
+        }
+    }
+
+    if stack.is_empty() {
+        Some(roots)
+    } else {
+        None
+    }
+}
+
+fn encode_generated_ranges(
+    ranges: &[GeneratedRange],
+    sources: &[String],
+    names: &mut ArraySet,
+) -> String {
+    struct State<'a> {
+        items: Vec<(Position, Vec<i32>)>,
+        definition_source: i32,
+        definition_scope: i32,
+        sources: &'a [String],
+    }
+
+    fn walk(range: &GeneratedRange, state: &mut State, names: &mut ArraySet) {
+        let source_index = |source: &str| {
+            state
+                .sources
+                .iter()
+                .position(|it| it == source)
+                .map(|it| it as i32)
+        };
+        let definition = range
+            .definition
+            .as_ref()
+            .and_then(|it| Some((source_index(&it.source)?, it.index as i32)));
+        let call_site = range
+            .call_site
+            .as_ref()
+            .and_then(|it| Some((source_index(&it.source)?, &it.position)));
+
+        let mut flags = 0;
+        if definition.is_some() {
+            flags |= GENERATED_HAS_DEFINITION;
+        }
+        if call_site.is_some() {
+            flags |= GENERATED_HAS_CALL_SITE;
+        }
+        if range.is_stack_frame {
+            flags |= GENERATED_IS_STACK_FRAME;
+        }
+        if range.is_hidden {
+            flags |= GENERATED_IS_HIDDEN;
+        }
+
+        let mut values = vec![flags];
+        if let Some((source, scope)) = definition {
+            if source != state.definition_source {
+                values.push(source - state.definition_source);
+                values.push(scope);
+            } else {
+                values.push(0);
+                values.push(scope - state.definition_scope);
+            }
+            state.definition_source = source;
+            state.definition_scope = scope;
+        }
+        if let Some((source, position)) = call_site {
+            values.extend_from_slice(&[source, position.line - 1, position.column]);
+        }
+        for binding in &range.bindings {
+            values.push(match binding {
+                Some(binding) => name_index(names, binding),
+                None => -1,
+            });
+        }
+        state.items.push((range.start.clone(), values));
+
+        for child in &range.children {
+            walk(child, state, names);
+        }
+
+        state.items.push((range.end.clone(), vec![]));
+    }
+
+    let mut state = State {
+        items: vec![],
+        definition_source: 0,
+        definition_scope: 0,
+        sources,
+    };
+    for range in ranges {
+        walk(range, &mut state, names);
+    }
+
+    let mut result = String::new();
+    let mut line = 1;
+    let mut column = 0;
+    let mut first_on_line = true;
+    for (position, values) in state.items {
+        while line < position.line {
+            result.push(';');
+            line += 1;
+            column = 0;
+            first_on_line = true;
+        }
+        if !first_on_line {
+            result.push(',');
+        }
+        first_on_line = false;
+        result += &base64vlq_encode(position.column - column);
+        result += &encode_item(&values);
+        column = position.column;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn position(line: i32, column: i32) -> Position {
+        Position { line, column }
+    }
+
+    fn example() -> Scopes {
+        let mut original = BTreeMap::new();
+        original.insert(
+            "a.js".to_string(),
+            vec![OriginalScope {
+                start: position(1, 0),
+                end: position(10, 0),
+                kind: Some("global".to_string()),
+                variables: vec!["foo".to_string()],
+                children: vec![OriginalScope {
+                    start: position(2, 0),
+                    end: position(5, 1),
+                    kind: Some("function".to_string()),
+                    name: Some("foo".to_string()),
+                    is_stack_frame: true,
+                    variables: vec!["x".to_string(), "y".to_string()],
+                    children: vec![],
+                }],
+                ..Default::default()
+            }],
+        );
+
+        Scopes {
+            original,
+            generated: vec![GeneratedRange {
+                start: position(1, 0),
+                end: position(3, 0),
+                definition: Some(ScopeReference {
+                    source: "a.js".to_string(),
+                    index: 0,
+                }),
+                bindings: vec![Some("f".to_string())],
+                children: vec![GeneratedRange {
+                    start: position(1, 10),
+                    end: position(2, 20),
+                    definition: Some(ScopeReference {
+                        source: "a.js".to_string(),
+                        index: 1,
+                    }),
+                    call_site: Some(CallSite {
+                        source: "a.js".to_string(),
+                        position: position(8, 2),
+                    }),
+                    is_stack_frame: true,
+                    bindings: vec![Some("a".to_string()), None],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let scopes = example();
+        let sources = vec!["a.js".to_string(), "b.js".to_string()];
+        let mut names = ArraySet::new();
+        let (original_scopes, generated_ranges) = scopes.encode(&sources, &mut names);
+        assert_eq!(original_scopes[1], "");
+
+        let decoded = Scopes::decode(
+            &original_scopes,
+            &generated_ranges,
+            &sources,
+            &names.to_vec(),
+        )
+        .unwrap();
+        assert_eq!(decoded, scopes);
+    }
+
+    #[test]
+    fn lookups() {
+        let scopes = example();
+        assert_eq!(
+            scopes
+                .original_scope_at(&position(1, 5))
+                .unwrap()
+                .kind
+                .as_deref(),
+            Some("global")
+        );
+        assert_eq!(
+            scopes
+                .original_scope_at(&position(2, 0))
+                .unwrap()
+                .name
+                .as_deref(),
+            Some("foo")
+        );
+        assert_eq!(scopes.original_function_name_at(&position(1, 5)), None);
+        assert_eq!(
+            scopes.original_function_name_at(&position(2, 19)),
+            Some("foo")
+        );
+        assert_eq!(scopes.ranges_at(&position(2, 20)).len(), 1);
+        assert_eq!(scopes.original_scope_at(&position(3, 0)), None);
+    }
+
+    #[test]
+    fn malformed() {
+        let sources = vec!["a.js".to_string()];
+        // An unclosed scope, a name index out of range and a bad definition.
+        assert_eq!(
+            Scopes::decode(&["AAA".to_string()], "", &sources, &[]),
+            None
+        );
+        assert_eq!(
+            Scopes::decode(&["AACC,CA".to_string()], "", &sources, &[]),
+            None
+        );
+        assert_eq!(Scopes::decode(&[], "ACCA,C", &sources, &[]), None);
+
+        // Deltas that overflow: lines, columns, the definition and the call
+        // site line.
+        assert_eq!(
+            Scopes::decode(&["+/////DAA,+/////DA".to_string()], "", &sources, &[]),
+            None
+        );
+        assert_eq!(
+            Scopes::decode(&[], "+/////DA,+/////DA", &sources, &[]),
+            None
+        );
+        assert_eq!(
+            Scopes::decode(&[], "ACA+/////D,ACA+/////D", &sources, &[]),
+            None
+        );
+        assert_eq!(Scopes::decode(&[], "AEA+/////DA", &sources, &[]), None);
+    }
+}
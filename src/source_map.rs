@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct SourceMapJson {
     pub version: i32,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -13,11 +14,93 @@ pub struct SourceMapJson {
     #[serde(rename = "sourceRoot")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_root: Option<String>,
+    /// The content of each source, `None` where the map doesn't carry it.
     #[serde(rename = "sourcesContent")]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub sources_content: Option<Vec<String>>,
+    pub sources_content: Option<Vec<Option<String>>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sections: Option<Vec<Section>>,
+    /// Indexes into `sources` of third-party or generated code that debuggers
+    /// should skip.
+    ///
+    /// ref: https://tc39.es/ecma426/#sec-source-map-format
+    #[serde(rename = "ignoreList")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore_list: Option<Vec<u32>>,
+    /// The name `ignoreList` had before it was standardized, still emitted by
+    /// some tools.
+    #[serde(rename = "x_google_ignoreList")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_google_ignore_list: Option<Vec<u32>>,
+    /// A UUID shared with the generated file's `//# debugId=` comment, used to
+    /// match the two without relying on URLs.
+    ///
+    /// ref: https://github.com/tc39/ecma426/blob/main/proposals/debug-id.md
+    #[serde(rename = "debugId", alias = "debug_id")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug_id: Option<String>,
+    /// The scopes proposal, see `crate::scopes`.
+    ///
+    /// ref: https://github.com/tc39/ecma426/blob/main/proposals/scopes.md
+    #[serde(rename = "originalScopes")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_scopes: Option<Vec<String>>,
+    #[serde(rename = "generatedRanges")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_ranges: Option<String>,
+    /// Which mappings are range mappings, see `crate::range_mappings`.
+    ///
+    /// ref: https://github.com/tc39/ecma426/blob/main/proposals/range-mappings.md
+    #[serde(rename = "rangeMappings")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_mappings: Option<String>,
+    /// Metro (React Native) metadata for each source, see
+    /// `crate::function_map`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_facebook_sources: Option<Vec<Option<SourceMetadata>>>,
+    /// Metro's byte offsets of each module in a RAM bundle, by module ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_facebook_offsets: Option<Vec<Option<u32>>>,
+    /// Metro's paths of the modules in a bundle, by module ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_metro_module_paths: Option<Vec<String>>,
+    /// Hermes' bytecode offset of each function, by segment ID. See
+    /// `crate::hermes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x_hermes_function_offsets: Option<BTreeMap<String, Vec<u32>>>,
+}
+
+impl SourceMapJson {
+    /// `ignoreList`, or `x_google_ignoreList` when only that one is present.
+    pub fn ignored_source_indexes(&self) -> &[u32] {
+        self.ignore_list
+            .as_ref()
+            .or(self.x_google_ignore_list.as_ref())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The Metro function map of the source at `source_index`, if any.
+    pub fn function_map(&self, source_index: usize) -> Option<&FunctionMap> {
+        self.x_facebook_sources
+            .as_ref()?
+            .get(source_index)?
+            .as_ref()?
+            .first()?
+            .as_ref()
+    }
+}
+
+/// Metro's metadata for a source: a tuple whose only entry so far is the
+/// function map.
+pub type SourceMetadata = Vec<Option<FunctionMap>>;
+
+/// The ranges of an original source covered by each function, named the way
+/// Metro's symbolicator shows them. See `crate::function_map`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct FunctionMap {
+    pub names: Vec<String>,
+    pub mappings: String,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -29,7 +112,7 @@ pub struct Section {
     pub url: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct Position {
     pub line: i32,
     pub column: i32,
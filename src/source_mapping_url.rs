@@ -0,0 +1,297 @@
+use crate::base64;
+use crate::generator::SourceMapGenerator;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref JS_COMMENT_REGEXP: Regex =
+        Regex::new(r"(?m)^[ \t]*//[#@][ \t]+sourceMappingURL=[^\s'\x22]*[ \t]*(\r?\n)?").unwrap();
+    static ref CSS_COMMENT_REGEXP: Regex =
+        Regex::new(r"(?m)^[ \t]*/\*[#@][ \t]+sourceMappingURL=[^\s*]*[ \t]*\*/[ \t]*(\r?\n)?")
+            .unwrap();
+    static ref DEBUG_ID_REGEXP: Regex = Regex::new(
+        r"(?m)^[ \t]*(?://# debugId=([^\s'\x22]+)|/\*# debugId=([^\s*]+)[ \t]*\*/)[ \t]*(\r?\n)?"
+    )
+    .unwrap();
+}
+
+const DATA_URL_PREFIX: &str = "data:application/json;charset=utf-8;base64,";
+
+/// The comment syntax used to attach a `sourceMappingURL` to generated code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// `//# sourceMappingURL=...`
+    Js,
+    /// `/*# sourceMappingURL=... */`
+    Css,
+}
+
+impl CommentStyle {
+    /// Pick the comment style from the generated file's extension. Anything that
+    /// is not a stylesheet gets the JS line comment.
+    ///
+    /// # Examples
+    /// ```
+    /// use rusty_source_map::source_mapping_url::CommentStyle;
+    /// assert_eq!(CommentStyle::from_path("dist/app.css"), CommentStyle::Css);
+    /// assert_eq!(CommentStyle::from_path("dist/app.mjs"), CommentStyle::Js);
+    /// ```
+    pub fn from_path(path: &str) -> Self {
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        match path.rsplit('.').next().map(|ext| ext.to_ascii_lowercase()) {
+            Some(ext) if ["css", "scss", "sass", "less", "styl"].contains(&ext.as_str()) => {
+                CommentStyle::Css
+            }
+            _ => CommentStyle::Js,
+        }
+    }
+
+    /// Render a `debugId` comment.
+    pub fn debug_id_comment(&self, debug_id: &str) -> String {
+        match self {
+            CommentStyle::Js => format!("//# debugId={}", debug_id),
+            CommentStyle::Css => format!("/*# debugId={} */", debug_id),
+        }
+    }
+
+    /// Render a `sourceMappingURL` comment pointing at `url`.
+    pub fn comment(&self, url: &str) -> String {
+        match self {
+            CommentStyle::Js => format!("//# sourceMappingURL={}", url),
+            CommentStyle::Css => format!("/*# sourceMappingURL={} */", url),
+        }
+    }
+}
+
+/// Serialize the generator as a base64 `data:` URL suitable for inlining.
+pub fn to_data_url(generator: &mut SourceMapGenerator) -> String {
+    format!(
+        "{}{}",
+        DATA_URL_PREFIX,
+        base64::encode_bytes(generator.as_string().as_bytes())
+    )
+}
+
+/// Remove every `sourceMappingURL` comment, in either style, from `code`.
+pub fn remove_source_mapping_url(code: &str) -> String {
+    let code = JS_COMMENT_REGEXP.replace_all(code, "");
+    CSS_COMMENT_REGEXP.replace_all(&code, "").into_owned()
+}
+
+/// Attach `url` to `code` as a `sourceMappingURL` comment, replacing any
+/// comment that is already there.
+pub fn set_source_mapping_url(code: &str, url: &str, style: CommentStyle) -> String {
+    let mut result = remove_source_mapping_url(code);
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(&style.comment(url));
+    result.push('\n');
+    result
+}
+
+/// Append the map as an inline base64 `data:` URL comment.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::generator::SourceMapGenerator;
+/// use rusty_source_map::source_mapping_url::{inline_source_map, CommentStyle};
+///
+/// let mut generator = SourceMapGenerator::new(Some("a.js".to_owned()), None, false);
+/// let code = inline_source_map("a();", &mut generator, CommentStyle::Js);
+/// assert!(code.starts_with("a();\n//# sourceMappingURL=data:application/json;"));
+/// ```
+pub fn inline_source_map(
+    code: &str,
+    generator: &mut SourceMapGenerator,
+    style: CommentStyle,
+) -> String {
+    set_source_mapping_url(code, &to_data_url(generator), style)
+}
+
+/// Append a comment pointing at an external map file. `file` is the path of
+/// the generated file and `map_file` the path the map is written to; the URL in
+/// the comment is `map_file` relative to the directory of `file`.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::source_mapping_url::{external_source_map, CommentStyle};
+///
+/// let code = external_source_map("a {}", "dist/a.css", "dist/maps/a.css.map", CommentStyle::Css);
+/// assert_eq!(code, "a {}\n/*# sourceMappingURL=maps/a.css.map */\n");
+/// ```
+pub fn external_source_map(code: &str, file: &str, map_file: &str, style: CommentStyle) -> String {
+    set_source_mapping_url(code, &relative_to_file(file, map_file), style)
+}
+
+/// The ID in the last `debugId` comment of `code`, in either style.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::source_mapping_url::find_debug_id;
+///
+/// let code = "a();\n//# debugId=85314830-023f-4cf1-a267-535f4e37bb17\n";
+/// assert_eq!(
+///     find_debug_id(code).as_deref(),
+///     Some("85314830-023f-4cf1-a267-535f4e37bb17")
+/// );
+/// ```
+pub fn find_debug_id(code: &str) -> Option<String> {
+    let captures = DEBUG_ID_REGEXP.captures_iter(code).last()?;
+    captures
+        .get(1)
+        .or_else(|| captures.get(2))
+        .map(|it| it.as_str().to_string())
+}
+
+/// Remove every `debugId` comment, in either style, from `code`.
+pub fn remove_debug_id(code: &str) -> String {
+    DEBUG_ID_REGEXP.replace_all(code, "").into_owned()
+}
+
+/// Attach `debug_id` to `code` as a `debugId` comment, replacing any comment
+/// that is already there. It goes right before the `sourceMappingURL` comment
+/// if there is one, and at the end otherwise.
+pub fn set_debug_id(code: &str, debug_id: &str, style: CommentStyle) -> String {
+    let code = remove_debug_id(code);
+    let comment = style.debug_id_comment(debug_id);
+
+    let url_comment = JS_COMMENT_REGEXP
+        .find_iter(&code)
+        .chain(CSS_COMMENT_REGEXP.find_iter(&code))
+        .map(|it| it.start())
+        .max();
+    match url_comment {
+        Some(start) => format!("{}{}\n{}", &code[..start], comment, &code[start..]),
+        None => {
+            let mut result = code;
+            if !result.is_empty() && !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str(&comment);
+            result.push('\n');
+            result
+        }
+    }
+}
+
+fn relative_to_file(file: &str, target: &str) -> String {
+    if target.contains("://") || target.starts_with("data:") {
+        return target.to_string();
+    }
+
+    let segments = |path: &str| -> Vec<String> {
+        path.replace('\\', "/")
+            .split('/')
+            .filter(|it| !it.is_empty() && *it != ".")
+            .map(|it| it.to_string())
+            .collect()
+    };
+
+    let mut from = segments(file);
+    from.pop();
+    let to = segments(target);
+
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    std::iter::repeat_n("..".to_string(), from.len() - common)
+        .chain(to.into_iter().skip(common))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replaces_existing_comment() {
+        let code = "a();\n//# sourceMappingURL=old.js.map\n";
+        assert_eq!(
+            set_source_mapping_url(code, "new.js.map", CommentStyle::Js),
+            "a();\n//# sourceMappingURL=new.js.map\n"
+        );
+
+        let code = "a();\n//@ sourceMappingURL=old.js.map\nb();";
+        assert_eq!(
+            set_source_mapping_url(code, "new.js.map", CommentStyle::Js),
+            "a();\nb();\n//# sourceMappingURL=new.js.map\n"
+        );
+
+        let code = "a{}\n/*# sourceMappingURL=old.css.map */";
+        assert_eq!(
+            set_source_mapping_url(code, "new.css.map", CommentStyle::Css),
+            "a{}\n/*# sourceMappingURL=new.css.map */\n"
+        );
+    }
+
+    #[test]
+    fn leaves_strings_alone() {
+        let code = "const s = \"//# sourceMappingURL=\" + url;";
+        assert_eq!(remove_source_mapping_url(code), code);
+    }
+
+    #[test]
+    fn relative_urls() {
+        assert_eq!(relative_to_file("a.js", "a.js.map"), "a.js.map");
+        assert_eq!(relative_to_file("dist/a.js", "dist/a.js.map"), "a.js.map");
+        assert_eq!(
+            relative_to_file("dist/js/a.js", "dist/maps/a.js.map"),
+            "../maps/a.js.map"
+        );
+        assert_eq!(
+            relative_to_file("/out/a.js", "https://cdn.example.com/a.js.map"),
+            "https://cdn.example.com/a.js.map"
+        );
+    }
+
+    #[test]
+    fn inline_round_trip() {
+        let mut generator = SourceMapGenerator::new(Some("a.js".to_string()), None, false);
+        let code = inline_source_map("a();", &mut generator, CommentStyle::Js);
+        let url = code
+            .trim_end()
+            .rsplit("sourceMappingURL=")
+            .next()
+            .unwrap()
+            .to_string();
+        assert_eq!(url, to_data_url(&mut generator));
+        assert!(url.starts_with(DATA_URL_PREFIX));
+    }
+
+    #[test]
+    fn debug_id_comments() {
+        let id = "85314830-023f-4cf1-a267-535f4e37bb17";
+
+        let code = set_debug_id("a();", id, CommentStyle::Js);
+        assert_eq!(code, format!("a();\n//# debugId={}\n", id));
+        assert_eq!(find_debug_id(&code).as_deref(), Some(id));
+
+        // Goes before the sourceMappingURL comment and replaces the old ID.
+        let code = "a();\n//# debugId=old\n//# sourceMappingURL=a.js.map\n";
+        assert_eq!(
+            set_debug_id(code, id, CommentStyle::Js),
+            format!("a();\n//# debugId={}\n//# sourceMappingURL=a.js.map\n", id)
+        );
+
+        let code = set_debug_id(
+            "a{}\n/*# sourceMappingURL=a.css.map */",
+            id,
+            CommentStyle::Css,
+        );
+        assert_eq!(
+            code,
+            format!(
+                "a{{}}\n/*# debugId={} */\n/*# sourceMappingURL=a.css.map */",
+                id
+            )
+        );
+        assert_eq!(find_debug_id(&code).as_deref(), Some(id));
+
+        assert_eq!(find_debug_id("const s = \"//# debugId=x\";"), None);
+    }
+}
@@ -42,13 +42,13 @@ pub fn compare_by_generated_pos_inflated(a: &Mapping, b: &Mapping) -> i32 {
     if cmp != 0 {
         return cmp;
     }
-    if a.original.is_some() && b.original.is_some() {
-        cmp = a.original.as_ref().unwrap().line - b.original.as_ref().unwrap().line;
+    if let (Some(a_original), Some(b_original)) = (a.original.as_ref(), b.original.as_ref()) {
+        cmp = a_original.line - b_original.line;
         if cmp != 0 {
             return cmp;
         }
 
-        cmp = a.original.as_ref().unwrap().column - b.original.as_ref().unwrap().column;
+        cmp = a_original.column - b_original.column;
         if cmp != 0 {
             return cmp;
         }
@@ -77,13 +77,10 @@ enum UrlType {
 }
 
 fn get_url_type(input: &str) -> UrlType {
-    let mut iter = input.chars();
-    let first = iter.next().unwrap();
-    let second = iter.next().unwrap();
-    if first == '/' {
-        if second == '/' {
-            return SchemeRelative;
-        }
+    if input.starts_with("//") {
+        return SchemeRelative;
+    }
+    if input.starts_with('/') {
         return PathAbsolute;
     }
     if ABSOLUTE_SCHEME_REGEXP.is_match(input) {
@@ -356,3 +353,62 @@ pub fn compute_source_url(
 
     url
 }
+
+/// Compile a glob such as `node_modules/**` or `**/*.min.js` into a regex that
+/// matches a source path or URL ending in it. `**` matches across `/`, `*` and
+/// `?` don't. A pattern without a leading `/` may start at any path segment.
+pub fn glob_to_regex(pattern: &str) -> Regex {
+    let mut regex = String::from(if pattern.starts_with('/') {
+        "^"
+    } else {
+        "(?:^|/)"
+    });
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches no directory at all.
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).unwrap()
+}
+
+#[test]
+fn test_glob_to_regex() {
+    let node_modules = glob_to_regex("node_modules/**");
+    assert!(node_modules.is_match("node_modules/react/index.js"));
+    assert!(node_modules.is_match("webpack:///./node_modules/react/index.js"));
+    assert!(!node_modules.is_match("src/my_node_modules/a.js"));
+    assert!(!node_modules.is_match("src/index.js"));
+
+    let minified = glob_to_regex("**/*.min.js");
+    assert!(minified.is_match("a.min.js"));
+    assert!(minified.is_match("/vendor/lib/a.min.js"));
+    assert!(!minified.is_match("a.min.js.map"));
+
+    let rooted = glob_to_regex("/vendor/?.js");
+    assert!(rooted.is_match("/vendor/a.js"));
+    assert!(!rooted.is_match("/src/vendor/a.js"));
+    assert!(!rooted.is_match("/vendor/ab.js"));
+}
+
+#[test]
+fn test_get_url_type() {
+    assert_eq!(get_url_type(""), PathRelative);
+    assert_eq!(get_url_type("a"), PathRelative);
+    assert_eq!(get_url_type("/"), PathAbsolute);
+    assert_eq!(get_url_type("//a"), SchemeRelative);
+    assert_eq!(get_url_type("https://a"), Absolute);
+}
@@ -0,0 +1,261 @@
+//!
+//! Render a source map as a standalone HTML page that shows the generated code
+//! next to its original sources, like evanw's source-map-visualization.
+//!
+//! ref: https://evanw.github.io/source-map-visualization/
+//!
+use crate::consumer::{BasicConsumer, ConsumerTrait, IterOrd};
+use crate::line_index::{ColumnUnit, LineIndex};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// Number of `c0`..`cN` colour classes defined in `STYLE`.
+const COLORS: usize = 6;
+
+const STYLE: &str = "
+body { margin: 0; font: 12px/1.5 ui-monospace, Menlo, Consolas, monospace; color: #222; }
+header { padding: 6px 12px; background: #eee; border-bottom: 1px solid #ccc; }
+main { display: flex; height: calc(100vh - 34px); }
+.pane { flex: 1; overflow: auto; border-right: 1px solid #ccc; }
+h2 { position: sticky; top: 0; margin: 0; padding: 2px 12px; font-size: 12px; background: #f7f7f7; border-bottom: 1px solid #ddd; }
+pre { margin: 0; padding: 4px 0; counter-reset: line; }
+.line { white-space: pre; padding-right: 12px; }
+.line::before { counter-increment: line; content: counter(line); display: inline-block; width: 4em; padding-right: 1em; text-align: right; color: #aaa; }
+.missing { padding: 4px 12px; color: #999; font-style: italic; }
+.m { cursor: pointer; border-radius: 2px; }
+.c0 { background: #fde2e2; } .c1 { background: #fdf1d6; } .c2 { background: #e2f5e2; }
+.c3 { background: #dcecfb; } .c4 { background: #ece2fb; } .c5 { background: #fbe2f3; }
+.hl { background: #333; color: #fff; }
+";
+
+const SCRIPT: &str = "
+var highlighted = [];
+document.addEventListener('mouseover', function (event) {
+  var target = event.target.closest ? event.target.closest('[data-o]') : null;
+  highlighted.forEach(function (it) { it.classList.remove('hl'); });
+  highlighted = [];
+  if (!target) return;
+  var pane = target.closest('.pane');
+  var all = document.querySelectorAll('[data-o=\"' + target.dataset.o + '\"]');
+  var scrolled = false;
+  Array.prototype.forEach.call(all, function (it) {
+    it.classList.add('hl');
+    highlighted.push(it);
+    if (!scrolled && it.closest('.pane') !== pane) {
+      it.scrollIntoView({ block: 'nearest' });
+      scrolled = true;
+    }
+  });
+});
+";
+
+/// A highlighted span on one line, columns in UTF-16 code units.
+struct Span {
+    line: i32,
+    start: i32,
+    end: Option<i32>,
+    id: usize,
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+///
+/// Render `text` one `<div class="line">` per line, wrapping every span in a
+/// `<span data-o>` that links it to its counterparts. `spans` must be sorted
+/// and must not overlap.
+///
+fn render_text(text: &str, spans: &[Span]) -> String {
+    let index = LineIndex::new(text);
+    let mut html = String::from("<pre>");
+    let mut spans = spans.iter().peekable();
+
+    for line in 1..=index.line_count() as i32 {
+        let line_text = index.line(line).unwrap_or_default();
+        let to_byte = |column: i32| {
+            index
+                .convert_column(line, column, ColumnUnit::Utf16, ColumnUnit::Utf8)
+                .unwrap_or(column)
+                .clamp(0, line_text.len() as i32) as usize
+        };
+
+        html.push_str("<div class=\"line\">");
+        let mut cursor = 0;
+        while let Some(span) = spans.next_if(|it| it.line <= line) {
+            if span.line < line {
+                continue;
+            }
+            let start = to_byte(span.start).max(cursor);
+            let end = span.end.map(to_byte).unwrap_or(line_text.len());
+            if end <= start {
+                continue;
+            }
+            html.push_str(&escape(&line_text[cursor..start]));
+            html.push_str(&format!(
+                "<span class=\"m c{}\" data-o=\"{}\">{}</span>",
+                span.id % COLORS,
+                span.id,
+                escape(&line_text[start..end])
+            ));
+            cursor = end;
+        }
+        html.push_str(&escape(&line_text[cursor..]));
+        html.push_str("</div>\n");
+    }
+
+    html.push_str("</pre>");
+    html
+}
+
+///
+/// Render `code` and the original sources of `consumer` as a self-contained
+/// HTML page. Each mapped span of the generated code is colour-coded along
+/// with the original span it maps to, and hovering either one highlights the
+/// other. Original spans run from a mapped original position to the next one
+/// on the same line. Sources without `sourcesContent` are listed but empty.
+///
+/// The page has no external references, so it works offline.
+///
+pub fn render_html(code: &str, consumer: &mut BasicConsumer) -> String {
+    consumer.compute_column_spans();
+    let mappings = RefCell::new(vec![]);
+    consumer.each_mapping(
+        |it| mappings.borrow_mut().push(it.clone()),
+        IterOrd::GeneratedOrd,
+    );
+    let mappings = mappings.into_inner();
+
+    // One id per distinct original position, shared by all generated spans
+    // that map to it.
+    let mut ids: BTreeMap<(u32, u32, u32), usize> = BTreeMap::new();
+    for original in mappings.iter().filter_map(|it| it.original.as_ref()) {
+        let key = (
+            original.source,
+            original.original_line,
+            original.original_column,
+        );
+        let next = ids.len();
+        ids.entry(key).or_insert(next);
+    }
+
+    let generated_spans: Vec<Span> = mappings
+        .iter()
+        .filter_map(|it| {
+            let original = it.original.as_ref()?;
+            Some(Span {
+                line: (it.generated_line + 1) as i32,
+                start: it.generated_column as i32,
+                end: it.last_generated_column.map(|it| it as i32),
+                id: ids[&(
+                    original.source,
+                    original.original_line,
+                    original.original_column,
+                )],
+            })
+        })
+        .collect();
+
+    let mut original_spans: BTreeMap<u32, Vec<Span>> = BTreeMap::new();
+    let keys: Vec<_> = ids.iter().collect();
+    for (index, ((source, line, column), id)) in keys.iter().enumerate() {
+        let end = keys
+            .get(index + 1)
+            .filter(|((next_source, next_line, _), _)| next_source == source && next_line == line)
+            .map(|((_, _, next_column), _)| *next_column as i32);
+        original_spans.entry(*source).or_default().push(Span {
+            line: (*line + 1) as i32,
+            start: *column as i32,
+            end,
+            id: **id,
+        });
+    }
+
+    let title = consumer
+        .source_map
+        .file
+        .clone()
+        .unwrap_or_else(|| "source map".to_string());
+
+    let mut originals = String::new();
+    for (index, source) in consumer.get_sources().iter().enumerate() {
+        originals.push_str(&format!("<h2>{}</h2>\n", escape(source)));
+        match consumer.source_content_for(source, Some(false)) {
+            Some(content) => {
+                let spans = original_spans
+                    .get(&(index as u32))
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                originals.push_str(&render_text(&content, spans));
+            }
+            None => originals.push_str("<div class=\"missing\">(no sourcesContent)</div>"),
+        }
+        originals.push('\n');
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n<header>{title}</header>\n<main>\n<section class=\"pane\">\n<h2>Generated</h2>\n{generated}\n</section>\n<section class=\"pane\">\n{originals}</section>\n</main>\n<script>{script}</script>\n</body>\n</html>\n",
+        title = escape(&title),
+        style = STYLE,
+        generated = render_text(code, &generated_spans),
+        originals = originals,
+        script = SCRIPT,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links_generated_and_original_spans() {
+        let code = "a();b(\"<\");";
+        let map = r#"{
+          "version": 3,
+          "file": "out.js",
+          "sources": ["a.js", "b.js"],
+          "sourcesContent": ["a();", "\n  b(\"<\");"],
+          "names": [],
+          "mappings": "AAAA,ICCE"
+        }"#;
+        let mut consumer = BasicConsumer::new(map, None);
+        let html = render_html(code, &mut consumer);
+
+        assert!(html.contains(
+            "<div class=\"line\"><span class=\"m c0\" data-o=\"0\">a();</span><span class=\"m c1\" data-o=\"1\">b(&quot;&lt;&quot;);</span></div>"
+        ));
+        assert!(html.contains("<h2>a.js</h2>\n<pre><div class=\"line\"><span class=\"m c0\" data-o=\"0\">a();</span></div>\n</pre>"));
+        assert!(html.contains(
+            "<div class=\"line\">  <span class=\"m c1\" data-o=\"1\">b(&quot;&lt;&quot;);</span></div>"
+        ));
+        assert!(html.contains("<title>out.js</title>"));
+
+        // Nothing is loaded from elsewhere.
+        assert!(!html.contains("src="));
+        assert!(!html.contains("href="));
+        assert!(!html.contains("url("));
+    }
+
+    #[test]
+    fn missing_sources_content() {
+        let map = r#"{
+          "version": 3,
+          "sources": ["a.js"],
+          "names": [],
+          "mappings": "AAAA"
+        }"#;
+        let mut consumer = BasicConsumer::new(map, None);
+        let html = render_html("a", &mut consumer);
+        assert!(html.contains("<h2>a.js</h2>\n<div class=\"missing\">(no sourcesContent)</div>"));
+    }
+}
@@ -0,0 +1,251 @@
+//!
+//! Read and rewrite the `sourceMappingURL` custom section of a WebAssembly
+//! module, which is how a `.wasm` file points to its source map.
+//!
+//! ref: https://webassembly.github.io/spec/core/binary/modules.html#binary-module
+//!
+//! Wasm maps have a single generated line, and use byte offsets as columns,
+//! see `BasicConsumer::original_position_for_wasm_offset`.
+//!
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+const MAGIC: &[u8] = b"\0asm";
+const HEADER_LENGTH: usize = 8;
+const CUSTOM_SECTION_ID: u8 = 0;
+const CODE_SECTION_ID: u8 = 10;
+const SOURCE_MAPPING_URL: &str = "sourceMappingURL";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WasmError {
+    /// The buffer doesn't start with the wasm magic number and version.
+    NotWasm,
+    /// A section header or a length at this offset is malformed or runs past
+    /// the end of the buffer.
+    Malformed(usize),
+    /// The name or URL of a custom section at this offset is not UTF-8.
+    InvalidUtf8(usize),
+}
+
+impl fmt::Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasmError::NotWasm => write!(f, "not a WebAssembly module"),
+            WasmError::Malformed(offset) => write!(f, "malformed module at offset {}", offset),
+            WasmError::InvalidUtf8(offset) => {
+                write!(f, "invalid UTF-8 in custom section at offset {}", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WasmError {}
+
+/// A section of a module: its ID, where it starts (at the ID) and the range
+/// of its contents.
+struct Section {
+    id: u8,
+    start: usize,
+    contents: Range<usize>,
+}
+
+/// Read an unsigned LEB128 `u32` at `offset`, returning it and the offset
+/// after it.
+fn read_u32(bytes: &[u8], offset: usize) -> Result<(u32, usize), WasmError> {
+    let mut result: u64 = 0;
+    for (index, byte) in bytes.iter().enumerate().skip(offset).take(5) {
+        result |= ((byte & 0x7f) as u64) << (7 * (index - offset));
+        if byte & 0x80 == 0 {
+            let value = u32::try_from(result).map_err(|_| WasmError::Malformed(offset))?;
+            return Ok((value, index + 1));
+        }
+    }
+    Err(WasmError::Malformed(offset))
+}
+
+fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Read a length-prefixed UTF-8 string at `offset`, returning it and the
+/// offset after it.
+fn read_string(bytes: &[u8], offset: usize) -> Result<(&str, usize), WasmError> {
+    let (length, start) = read_u32(bytes, offset)?;
+    let end = start
+        .checked_add(length as usize)
+        .filter(|it| *it <= bytes.len())
+        .ok_or(WasmError::Malformed(offset))?;
+    let string =
+        std::str::from_utf8(&bytes[start..end]).map_err(|_| WasmError::InvalidUtf8(offset))?;
+    Ok((string, end))
+}
+
+fn write_string(out: &mut Vec<u8>, string: &str) {
+    write_u32(out, string.len() as u32);
+    out.extend_from_slice(string.as_bytes());
+}
+
+fn sections(bytes: &[u8]) -> Result<Vec<Section>, WasmError> {
+    if bytes.len() < HEADER_LENGTH || &bytes[..4] != MAGIC {
+        return Err(WasmError::NotWasm);
+    }
+
+    let mut sections = vec![];
+    let mut offset = HEADER_LENGTH;
+    while offset < bytes.len() {
+        let (size, start) = read_u32(bytes, offset + 1)?;
+        let end = start
+            .checked_add(size as usize)
+            .filter(|it| *it <= bytes.len())
+            .ok_or(WasmError::Malformed(offset))?;
+        sections.push(Section {
+            id: bytes[offset],
+            start: offset,
+            contents: start..end,
+        });
+        offset = end;
+    }
+    Ok(sections)
+}
+
+/// The name of a custom section and the offset of its payload.
+fn custom_section_name<'a>(
+    bytes: &'a [u8],
+    section: &Section,
+) -> Result<(&'a str, usize), WasmError> {
+    let contents = &bytes[..section.contents.end];
+    read_string(contents, section.contents.start)
+}
+
+///
+/// The URL in the `sourceMappingURL` custom section of the module in `bytes`,
+/// if it has one.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::wasm::{set_source_mapping_url, source_mapping_url};
+///
+/// let module = b"\0asm\x01\0\0\0".to_vec();
+/// assert_eq!(source_mapping_url(&module), Ok(None));
+///
+/// let module = set_source_mapping_url(&module, Some("app.wasm.map")).unwrap();
+/// assert_eq!(source_mapping_url(&module), Ok(Some("app.wasm.map".to_string())));
+/// ```
+///
+pub fn source_mapping_url(bytes: &[u8]) -> Result<Option<String>, WasmError> {
+    for section in sections(bytes)? {
+        if section.id != CUSTOM_SECTION_ID {
+            continue;
+        }
+        let (name, payload) = custom_section_name(bytes, &section)?;
+        if name == SOURCE_MAPPING_URL {
+            let (url, _) = read_string(&bytes[..section.contents.end], payload)?;
+            return Ok(Some(url.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Copy the module in `bytes` with its `sourceMappingURL` custom sections
+/// removed and, if `url` is given, a new one appended.
+pub fn set_source_mapping_url(bytes: &[u8], url: Option<&str>) -> Result<Vec<u8>, WasmError> {
+    let mut out = bytes[..HEADER_LENGTH.min(bytes.len())].to_vec();
+    for section in sections(bytes)? {
+        if section.id == CUSTOM_SECTION_ID
+            && custom_section_name(bytes, &section)?.0 == SOURCE_MAPPING_URL
+        {
+            continue;
+        }
+        out.extend_from_slice(&bytes[section.start..section.contents.end]);
+    }
+
+    if let Some(url) = url {
+        let mut contents = vec![];
+        write_string(&mut contents, SOURCE_MAPPING_URL);
+        write_string(&mut contents, url);
+        out.push(CUSTOM_SECTION_ID);
+        write_u32(&mut out, contents.len() as u32);
+        out.extend(contents);
+    }
+    Ok(out)
+}
+
+/// The byte range of the contents of the code section, for converting
+/// between offsets in the module and offsets in the code section.
+pub fn code_section_range(bytes: &[u8]) -> Result<Option<Range<usize>>, WasmError> {
+    Ok(sections(bytes)?
+        .into_iter()
+        .find(|it| it.id == CODE_SECTION_ID)
+        .map(|it| it.contents))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn module() -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        // A type section and a code section, with made up contents.
+        module.extend_from_slice(&[1, 2, 0xaa, 0xbb]);
+        module.extend_from_slice(&[CODE_SECTION_ID, 3, 1, 2, 3]);
+        // A `name` custom section.
+        module.extend_from_slice(&[0, 6, 4]);
+        module.extend_from_slice(b"name\x00");
+        module
+    }
+
+    #[test]
+    fn leb128() {
+        for value in [0, 1, 127, 128, 300, u32::MAX] {
+            let mut out = vec![0xff];
+            write_u32(&mut out, value);
+            assert_eq!(read_u32(&out, 1), Ok((value, out.len())));
+        }
+        assert_eq!(read_u32(&[0x80, 0x80], 0), Err(WasmError::Malformed(0)));
+        assert_eq!(
+            read_u32(&[0xff, 0xff, 0xff, 0xff, 0x7f], 0),
+            Err(WasmError::Malformed(0))
+        );
+    }
+
+    #[test]
+    fn rewrite() {
+        let module = module();
+        assert_eq!(code_section_range(&module), Ok(Some(14..17)));
+        assert_eq!(source_mapping_url(&module), Ok(None));
+
+        let long_url = format!("https://example.com/{}.wasm.map", "a".repeat(200));
+        let with_url = set_source_mapping_url(&module, Some("first.map")).unwrap();
+        let with_url = set_source_mapping_url(&with_url, Some(&long_url)).unwrap();
+        assert_eq!(source_mapping_url(&with_url), Ok(Some(long_url)));
+        assert_eq!(code_section_range(&with_url), Ok(Some(14..17)));
+
+        assert_eq!(set_source_mapping_url(&with_url, None), Ok(module));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            source_mapping_url(b"\0asx\x01\0\0\0"),
+            Err(WasmError::NotWasm)
+        );
+        assert_eq!(source_mapping_url(b"\0asm"), Err(WasmError::NotWasm));
+        assert_eq!(
+            source_mapping_url(b"\0asm\x01\0\0\0\x01\x05\0"),
+            Err(WasmError::Malformed(8))
+        );
+        assert_eq!(
+            source_mapping_url(b"\0asm\x01\0\0\0\0\x02\x01\xff"),
+            Err(WasmError::InvalidUtf8(10))
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//!
+//! An output writer for code generators that keeps track of the generated
+//! position and records mappings as mapped text is written.
+//!
+use crate::generator::SourceMapGenerator;
+use crate::line_index::Cursor;
+use crate::mapping::Mapping;
+use crate::source_map::Position;
+use std::fmt;
+use std::io;
+
+/// Where a `MappedWriter` sends its text.
+pub trait Output {
+    fn write_text(&mut self, text: &str) -> io::Result<()>;
+}
+
+impl Output for String {
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.push_str(text);
+        Ok(())
+    }
+}
+
+/// Adapts an `io::Write` to `Output`.
+pub struct IoOutput<W>(pub W);
+
+impl<W: io::Write> Output for IoOutput<W> {
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.0.write_all(text.as_bytes())
+    }
+}
+
+///
+/// Wraps an `Output` and tracks the generated line and column of everything
+/// written through it, counting columns in UTF-16 code units. `\n`, `\r\n` and
+/// a lone `\r` all end a line, even when split across writes.
+///
+/// # Examples
+/// ```
+/// use rusty_source_map::source_map::Position;
+/// use rusty_source_map::writer::MappedWriter;
+///
+/// let mut writer = MappedWriter::new(String::new(), Some("out.js".to_string()));
+/// writer.write("const ").unwrap();
+/// writer
+///     .write_mapped("answer", "in.js", Position { line: 3, column: 4 }, Some("answer"))
+///     .unwrap();
+/// writer.write(" = 42;\n").unwrap();
+/// assert_eq!(writer.position(), &Position { line: 2, column: 0 });
+///
+/// let (code, mut map) = writer.finish();
+/// assert_eq!(code, "const answer = 42;\n");
+/// assert_eq!(
+///     map.as_string(),
+///     r#"{"version":3,"sources":["in.js"],"names":["answer"],"mappings":"MAEIA","file":"out.js"}"#
+/// );
+/// ```
+///
+pub struct MappedWriter<W: Output> {
+    out: W,
+    cursor: Cursor,
+    generator: SourceMapGenerator,
+}
+
+impl<W: Output> MappedWriter<W> {
+    pub fn new(out: W, file: Option<String>) -> Self {
+        MappedWriter {
+            out,
+            cursor: Cursor::new(),
+            generator: SourceMapGenerator::new(file, None, false),
+        }
+    }
+
+    /// The generated position the next write starts at.
+    pub fn position(&self) -> &Position {
+        &self.cursor.position
+    }
+
+    /// The generator mappings are recorded into, e.g. to set source contents.
+    pub fn generator(&mut self) -> &mut SourceMapGenerator {
+        &mut self.generator
+    }
+
+    /// Write `text` without recording a mapping for it.
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        self.out.write_text(text)?;
+        self.cursor.advance(text);
+        Ok(())
+    }
+
+    /// Write `text`, then record a mapping from where it starts to `original`
+    /// in `source`. Nothing is recorded if the write fails.
+    pub fn write_mapped(
+        &mut self,
+        text: &str,
+        source: &str,
+        original: Position,
+        name: Option<&str>,
+    ) -> io::Result<()> {
+        let generated = self.cursor.position.clone();
+        self.write(text)?;
+        self.generator.add_mapping(Mapping {
+            generated,
+            original: Some(original),
+            source: Some(source.to_string()),
+            name: name.map(|it| it.to_string()),
+            last_generated_column: None,
+        });
+        Ok(())
+    }
+
+    /// Write `text` and mark it as not coming from any source, ending the span
+    /// of the previous mapping.
+    pub fn write_unmapped(&mut self, text: &str) -> io::Result<()> {
+        let generated = self.cursor.position.clone();
+        self.write(text)?;
+        self.generator.add_mapping(Mapping {
+            generated,
+            original: None,
+            source: None,
+            name: None,
+            last_generated_column: None,
+        });
+        Ok(())
+    }
+
+    pub fn finish(self) -> (W, SourceMapGenerator) {
+        (self.out, self.generator)
+    }
+}
+
+impl<W: io::Write> MappedWriter<IoOutput<W>> {
+    pub fn from_io(out: W, file: Option<String>) -> Self {
+        MappedWriter::new(IoOutput(out), file)
+    }
+}
+
+/// Lets `write!` be used for unmapped text.
+impl<W: Output> fmt::Write for MappedWriter<W> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.write(text).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::consumer::BasicConsumer;
+    use std::fmt::Write;
+
+    #[test]
+    fn tracks_lines_and_utf16_columns() {
+        let mut writer = MappedWriter::new(String::new(), None);
+        writer.write("a😀").unwrap();
+        assert_eq!(writer.position(), &Position { line: 1, column: 3 });
+        writer.write("\r").unwrap();
+        writer.write("\nb\rc").unwrap();
+        assert_eq!(writer.position(), &Position { line: 3, column: 1 });
+        writeln!(writer, "{}", 42).unwrap();
+        assert_eq!(writer.position(), &Position { line: 4, column: 0 });
+    }
+
+    #[test]
+    fn records_mappings() {
+        let mut writer = MappedWriter::from_io(Vec::new(), None);
+        writer.write("  ").unwrap();
+        writer
+            .write_mapped("foo", "a.js", Position { line: 1, column: 0 }, Some("foo"))
+            .unwrap();
+        writer.write_unmapped(" /* generated */\n").unwrap();
+        writer
+            .write_mapped("bar", "b.js", Position { line: 5, column: 2 }, None)
+            .unwrap();
+
+        let (out, mut generator) = writer.finish();
+        assert_eq!(
+            String::from_utf8(out.0).unwrap(),
+            "  foo /* generated */\nbar"
+        );
+
+        let mut consumer = BasicConsumer::from_source_map(&mut generator, None);
+        let lookup = |consumer: &mut BasicConsumer, line, column| {
+            let mapping = consumer.original_position_for(Position { line, column }, None)?;
+            Some((mapping.source?, mapping.original?.line, mapping.name))
+        };
+        assert_eq!(
+            lookup(&mut consumer, 1, 3),
+            Some(("a.js".to_string(), 1, Some("foo".to_string())))
+        );
+        assert_eq!(lookup(&mut consumer, 1, 6), None);
+        assert_eq!(
+            lookup(&mut consumer, 2, 0),
+            Some(("b.js".to_string(), 5, None))
+        );
+    }
+
+    #[test]
+    fn failed_writes_record_nothing() {
+        struct Full;
+        impl Output for Full {
+            fn write_text(&mut self, _: &str) -> io::Result<()> {
+                Err(io::Error::other("full"))
+            }
+        }
+
+        let mut writer = MappedWriter::new(Full, None);
+        assert!(writer
+            .write_mapped("foo", "a.js", Position { line: 1, column: 0 }, None)
+            .is_err());
+        assert!(writer.write_unmapped("bar").is_err());
+        assert_eq!(writer.position(), &Position { line: 1, column: 0 });
+
+        let (_, mut generator) = writer.finish();
+        assert!(generator
+            .as_string()
+            .starts_with(r##"{"version":3,"sources":[],"names":[],"mappings":"""##));
+    }
+}